@@ -0,0 +1,141 @@
+use crate::audio::types::Dither;
+
+/// Коэффициенты фильтра формирования шума (Lipshitz, F-взвешенный, 44.1 кГц):
+/// шум квантования уводится в области, где слух наименее чувствителен
+const SHAPING_44K: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+/// Квантование f32 в целые сэмплы заданной разрядности с дизерингом.
+///
+/// Генератор шума детерминированный, поэтому повторный экспорт даёт
+/// побитово одинаковый файл.
+pub struct Quantizer {
+    scale: f64,
+    min: i32,
+    max: i32,
+    dither: Dither,
+    rng: u64,
+    /// Последние ошибки квантования для формирования шума
+    errors: [f64; SHAPING_44K.len()],
+}
+
+impl Quantizer {
+    pub fn new(bits: u32, dither: Dither) -> Self {
+        let full_scale = 1i64 << (bits - 1);
+        Self {
+            scale: full_scale as f64,
+            min: -full_scale as i32,
+            max: (full_scale - 1) as i32,
+            dither,
+            rng: 0x9E37_79B9_7F4A_7C15,
+            errors: [0.0; SHAPING_44K.len()],
+        }
+    }
+
+    /// Переводит сэмпл (-1.0..1.0) в целое значение с дизерингом.
+    ///
+    /// Значения, точно представимые в целевой разрядности (прежде всего
+    /// цифровая тишина между объявлениями), проходят без шума: дизеринг
+    /// там не нужен и только мешает сжатию FLAC.
+    pub fn quantize(&mut self, sample: f32) -> i32 {
+        let exact = sample as f64 * self.scale;
+        if exact.fract() == 0.0 {
+            self.errors = [0.0; SHAPING_44K.len()];
+            return (exact as i64).clamp(self.min as i64, self.max as i64) as i32;
+        }
+
+        let mut target = exact;
+        if self.dither == Dither::NoiseShaped {
+            target -= SHAPING_44K
+                .iter()
+                .zip(&self.errors)
+                .map(|(c, e)| c * e)
+                .sum::<f64>();
+        }
+
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            // Треугольное распределение ±1 младший разряд
+            Dither::Tpdf | Dither::NoiseShaped => self.uniform() + self.uniform() - 1.0,
+        };
+        let quantized = (target + noise).round();
+
+        if self.dither == Dither::NoiseShaped {
+            self.errors.rotate_right(1);
+            // Ошибка ограничена, чтобы перегрузка не раскачивала фильтр
+            self.errors[0] = (quantized - target).clamp(-2.0, 2.0);
+        }
+
+        (quantized as i64).clamp(self.min as i64, self.max as i64) as i32
+    }
+
+    /// Сэмпл, квантованный и возвращённый в f32 (для передачи в FFmpeg
+    /// без повторного округления на его стороне)
+    pub fn quantize_to_float(&mut self, sample: f32) -> f32 {
+        (self.quantize(sample) as f64 / self.scale) as f32
+    }
+
+    /// Равномерный шум в [0, 1) (xorshift64*)
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Синус чуть громче полной шкалы, чтобы проверить и ограничение
+    fn signal() -> impl Iterator<Item = f32> {
+        (0..20_000).map(|i| (i as f32 * 0.013).sin() * 1.05)
+    }
+
+    #[test]
+    fn output_stays_in_range() {
+        for bits in [16, 24] {
+            let (min, max) = (-(1i32 << (bits - 1)), (1i32 << (bits - 1)) - 1);
+            for dither in [Dither::None, Dither::Tpdf, Dither::NoiseShaped] {
+                let mut quantizer = Quantizer::new(bits, dither);
+                for sample in signal().chain([1.0, -1.0, 2.0, -2.0]) {
+                    let value = quantizer.quantize(sample);
+                    assert!(
+                        (min..=max).contains(&value),
+                        "{bits} бит, {dither:?}: {value}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn error_is_bounded_by_dither_amplitude() {
+        for (dither, limit) in [(Dither::None, 0.5), (Dither::Tpdf, 1.5)] {
+            let mut quantizer = Quantizer::new(16, dither);
+            for sample in signal().map(|s| s * 0.9) {
+                let error = quantizer.quantize(sample) as f64 - sample as f64 * 32768.0;
+                assert!(error.abs() <= limit, "{dither:?}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn exact_values_pass_without_noise() {
+        let mut quantizer = Quantizer::new(16, Dither::NoiseShaped);
+        assert_eq!(quantizer.quantize(0.25), 8192);
+        assert_eq!(quantizer.quantize(0.0), 0);
+        assert_eq!(quantizer.quantize(-1.0), -32768);
+        assert_eq!(quantizer.quantize_to_float(0.5), 0.5);
+    }
+
+    #[test]
+    fn noise_is_deterministic() {
+        let run = || {
+            let mut quantizer = Quantizer::new(16, Dither::Tpdf);
+            signal().map(|s| quantizer.quantize(s)).collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -1,5 +1,16 @@
+pub mod cancel;
+pub mod dither;
 pub mod processor;
+pub mod recurrence;
+pub mod schedule;
+pub mod timeline;
+pub mod timetable;
 pub mod types;
+pub mod wav;
+pub mod zone;
 
-pub use processor::AudioProcessor;
+pub use cancel::{CancelToken, Cancelled};
+pub use dither::Quantizer;
+pub use processor::{AudioProcessor, RenderedRecord, SourceCache};
 pub use types::*;
+pub use wav::Rf64Writer;
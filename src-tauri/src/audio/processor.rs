@@ -11,12 +11,74 @@ use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
+use crate::audio::cancel::CancelToken;
+use crate::audio::dither::Quantizer;
+use crate::audio::timeline::{Marker, PlacedEvent, SamplePos, Timeline};
 use crate::audio::types::*;
+use crate::audio::wav::{self, Rf64Writer};
+
+/// Декодированные источники (моно, частота процессора) по id источника
+pub type SourceCache = HashMap<String, Vec<f32>>;
+
+/// Длительность фейда в начале и конце объявления
+pub const FADE_SECONDS: f64 = 0.3;
+
+/// Отрендеренная запись и позиции прозвучавших в ней объявлений
+#[derive(Debug, Clone)]
+pub struct RenderedRecord {
+    pub samples: Vec<f32>,
+    /// Маркеры по возрастанию начала
+    pub markers: Vec<Marker>,
+    pub sample_rate: u32,
+}
+
+impl RenderedRecord {
+    /// Позиция в сэмплах записи в миллисекундах
+    pub fn ms(&self, position: usize) -> u64 {
+        position as u64 * 1000 / self.sample_rate as u64
+    }
+}
+
+/// Отдельно отрендеренный звучащий фрагмент и все его вхождения в запись
+#[derive(Debug, Clone)]
+pub struct RenderedClip {
+    /// Звук фрагмента (громкость, фейды и нормализация всей записи применены)
+    pub samples: Vec<f32>,
+    /// Вхождения по возрастанию начала; длина каждого равна длине фрагмента
+    pub markers: Vec<Marker>,
+    pub sample_rate: u32,
+}
+
+/// Всё, от чего зависит звук фрагмента: одинаковые ключи дают одинаковые сэмплы
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ClipKey {
+    source_id: String,
+    cut: (usize, usize),
+    length: SamplePos,
+    /// Сэмплы события, отброшенные до начала записи
+    skipped: SamplePos,
+    audible: usize,
+    gain: u32,
+    fade_in: bool,
+    fade_out: bool,
+}
 
 pub struct AudioProcessor {
     sample_rate: u32,
 }
 
+/// Множитель нормализации для пиковой амплитуды; `None`, если она не нужна
+fn normalization_factor(max_amplitude: f32) -> Option<f32> {
+    if max_amplitude > 0.95 {
+        let normalize_factor = 0.9 / max_amplitude;
+        log::info!("Применяем нормализацию с коэффициентом {normalize_factor:.3}");
+        Some(normalize_factor)
+    } else {
+        log::info!("Нормализация не требуется");
+        None
+    }
+}
+
 impl AudioProcessor {
     pub fn new() -> Self {
         Self {
@@ -24,6 +86,11 @@ impl AudioProcessor {
         }
     }
 
+    /// Частота дискретизации декодированных источников и отрендеренных записей
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     /// Декодирует аудиофайл в PCM данные
     pub fn decode_audio_file(&self, file_path: &str) -> Result<Vec<f32>> {
         let path = Path::new(file_path);
@@ -135,9 +202,17 @@ impl AudioProcessor {
                                 }
                             }
                         }
-                        _ => {
-                            // Для других форматов используем общий подход
-                            continue;
+                        other => {
+                            // Остальные форматы (S16, S24, S32, F64, ...) приводятся к f32
+                            let mut buf = other.make_equivalent::<f32>();
+                            other.convert(&mut buf);
+                            if buf.spec().channels.count() == 1 {
+                                samples.extend_from_slice(buf.chan(0));
+                            } else {
+                                for (l, r) in buf.chan(0).iter().zip(buf.chan(1).iter()) {
+                                    samples.push((l + r) * 0.5);
+                                }
+                            }
                         }
                     }
                 }
@@ -226,55 +301,41 @@ impl AudioProcessor {
         }
     }
 
-    /// Генерирует финальный аудиофайл для записи
-    pub async fn render_record(
+    /// Декодирует все источники один раз; кэш можно использовать для нескольких записей
+    pub fn load_sources(
         &self,
-        record_name: &str,
-        arrangements: &[Arrangement],
-        time_record: &TimeOfRecord,
         sources: &[Source],
-        progress_callback: impl Fn(ExportProgress) + Send + Sync,
-    ) -> Result<Vec<f32>> {
-        let start_time = time_record.start.timestamp_millis();
-        let end_time = time_record.end.timestamp_millis();
-        let duration_ms = end_time - start_time;
-        let duration_seconds = duration_ms as f64 / 1000.0;
-
-        log::info!(
-            "Рендеринг записи '{}': длительность {:.2} сек ({:.2} мин)",
-            record_name,
-            duration_seconds,
-            duration_seconds / 60.0
-        );
-
-        // Создаем буфер для финального аудио
-        let total_samples = (duration_seconds * self.sample_rate as f64) as usize;
-        let mut final_buffer = vec![0.0f32; total_samples];
-
-        log::info!(
-            "Создан буфер на {} сэмплов ({}x{} Hz)",
-            total_samples,
-            duration_seconds,
-            self.sample_rate
-        );
+        record_name: Option<&str>,
+        cancel: &CancelToken,
+        progress_callback: impl Fn(ExportProgress),
+    ) -> Result<SourceCache> {
+        let target = record_name
+            .map(|name| format!("для записи {name}"))
+            .unwrap_or_else(|| "для всех записей".to_string());
 
         progress_callback(ExportProgress {
-            stage: "loading".to_string(),
+            stage: ExportStage::Loading,
             progress: 0.0,
-            message: format!("Загрузка аудиофайлов для записи {record_name}"),
-            record_name: Some(record_name.to_string()),
+            message: format!("Загрузка аудиофайлов {target}"),
+            record_name: record_name.map(str::to_string),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
         });
 
-        // Кэшируем декодированные аудиофайлы
-        let mut audio_cache: HashMap<String, Vec<f32>> = HashMap::new();
+        let mut audio_cache = SourceCache::new();
 
         for (i, source) in sources.iter().enumerate() {
+            cancel.check()?;
             let progress = (i as f32 / sources.len() as f32) * 30.0;
             progress_callback(ExportProgress {
-                stage: "loading".to_string(),
+                stage: ExportStage::Loading,
                 progress,
                 message: format!("Декодирование файла: {}", source.title),
-                record_name: Some(record_name.to_string()),
+                record_name: record_name.map(str::to_string),
+                elapsed_ms: None,
+                eta_ms: None,
+                job_id: None,
             });
 
             match self.decode_audio_file(&source.file_path) {
@@ -287,121 +348,304 @@ impl AudioProcessor {
             }
         }
 
+        Ok(audio_cache)
+    }
+
+    /// Генерирует финальный аудиофайл для записи
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_record(
+        &self,
+        record_name: &str,
+        arrangements: &[Arrangement],
+        time_record: &TimeOfRecord,
+        sources: &[Source],
+        audio_cache: &SourceCache,
+        cancel: &CancelToken,
+        progress_callback: impl Fn(ExportProgress),
+    ) -> Result<RenderedRecord> {
+        let timeline = Timeline::new(time_record, self.sample_rate);
+        let duration_seconds = timeline.samples_to_seconds(timeline.len() as SamplePos);
+
+        log::info!(
+            "Рендеринг записи '{}': длительность {:.2} сек ({:.2} мин)",
+            record_name,
+            duration_seconds,
+            duration_seconds / 60.0
+        );
+
+        // Создаем буфер для финального аудио
+        let mut final_buffer = vec![0.0f32; timeline.len()];
+        let mut markers = Vec::new();
+
+        log::info!(
+            "Создан буфер на {} сэмплов ({}x{} Hz)",
+            timeline.len(),
+            duration_seconds,
+            self.sample_rate
+        );
+
         progress_callback(ExportProgress {
-            stage: "processing".to_string(),
+            stage: ExportStage::Processing,
             progress: 30.0,
             message: format!("Обработка объявлений для записи {record_name}"),
             record_name: Some(record_name.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
         });
 
         // Обрабатываем каждое объявление
         for (i, arrangement) in arrangements.iter().enumerate() {
+            cancel.check()?;
             let progress = 30.0 + (i as f32 / arrangements.len() as f32) * 60.0;
             progress_callback(ExportProgress {
-                stage: "processing".to_string(),
+                stage: ExportStage::Processing,
                 progress,
                 message: format!("Обработка объявления {}/{}", i + 1, arrangements.len()),
                 record_name: Some(record_name.to_string()),
+                elapsed_ms: None,
+                eta_ms: None,
+                job_id: None,
             });
 
-            // Находим соответствующий источник
-            let source = sources
-                .iter()
-                .find(|s| s.type_id == arrangement.type_id)
-                .context("Source not found for arrangement")?;
-
-            if let Some(source_samples) = audio_cache.get(&source.id) {
+            if let Some((event, source, source_samples)) =
+                self.place_arrangement(&timeline, arrangement, sources, audio_cache)?
+            {
                 self.apply_arrangement_to_buffer(
                     &mut final_buffer,
                     source_samples,
-                    source,
-                    arrangement,
-                    start_time,
-                )?;
+                    &event,
+                    &timeline,
+                );
+                markers.extend(event.marker(source, timeline.len()));
             }
         }
 
         progress_callback(ExportProgress {
-            stage: "processing".to_string(),
+            stage: ExportStage::Processing,
             progress: 90.0,
             message: "Нормализация аудио".to_string(),
             record_name: Some(record_name.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
         });
 
         // Нормализуем громкость
         self.normalize_audio(&mut final_buffer);
 
-        Ok(final_buffer)
+        markers.sort_by_key(|marker| marker.range.start);
+        Ok(RenderedRecord {
+            samples: final_buffer,
+            markers,
+            sample_rate: self.sample_rate,
+        })
     }
 
-    /// Применяет объявление к финальному буферу
-    fn apply_arrangement_to_buffer(
+    /// Позиции объявлений записи без рендеринга звука — те же, что даёт
+    /// `render_record`
+    pub fn layout_record(
         &self,
-        final_buffer: &mut [f32],
-        source_samples: &[f32],
-        source: &Source,
-        arrangement: &Arrangement,
-        record_start_ms: i64,
-    ) -> Result<()> {
-        let arrangement_start_ms = arrangement.playing_time.start.timestamp_millis();
-        let arrangement_end_ms = arrangement.playing_time.end.timestamp_millis();
+        arrangements: &[Arrangement],
+        time_record: &TimeOfRecord,
+        sources: &[Source],
+        audio_cache: &SourceCache,
+    ) -> Result<Vec<Marker>> {
+        let timeline = Timeline::new(time_record, self.sample_rate);
+        let mut markers = Vec::new();
+        for arrangement in arrangements {
+            if let Some((event, source, _)) =
+                self.place_arrangement(&timeline, arrangement, sources, audio_cache)?
+            {
+                markers.extend(event.marker(source, timeline.len()));
+            }
+        }
+        markers.sort_by_key(|marker| marker.range.start);
+        Ok(markers)
+    }
 
-        // Вычисляем смещение от начала записи
-        let offset_ms = arrangement_start_ms - record_start_ms;
-        let offset_samples = ((offset_ms as f64 / 1000.0) * self.sample_rate as f64) as usize;
+    /// Рендерит каждый различный звучащий фрагмент записи один раз, без
+    /// буфера всей записи. Размещение то же, что в `render_record`, и
+    /// нормализация тоже: пик считается по смеси всей записи, поэтому
+    /// громкость фрагмента совпадает с громкостью события в полном экспорте.
+    pub fn render_clips(
+        &self,
+        arrangements: &[Arrangement],
+        time_record: &TimeOfRecord,
+        sources: &[Source],
+        audio_cache: &SourceCache,
+        cancel: &CancelToken,
+    ) -> Result<Vec<RenderedClip>> {
+        let timeline = Timeline::new(time_record, self.sample_rate);
+        let mut placed = Vec::new();
+        for arrangement in arrangements {
+            cancel.check()?;
+            if let Some((event, source, source_samples)) =
+                self.place_arrangement(&timeline, arrangement, sources, audio_cache)?
+            {
+                if let Some(marker) = event.marker(source, timeline.len()) {
+                    placed.push((event, source, source_samples, marker));
+                }
+            }
+        }
+        let peak = self.mix_peak(&placed, &timeline, cancel)?;
+        let normalize_factor = normalization_factor(peak);
+
+        let mut clips: Vec<RenderedClip> = Vec::new();
+        let mut index: HashMap<ClipKey, usize> = HashMap::new();
+        for (event, source, source_samples, marker) in placed {
+            cancel.check()?;
+            let skipped = marker.range.start as SamplePos - event.start;
+            let key = ClipKey {
+                source_id: source.id.clone(),
+                cut: (event.cut.start, event.cut.end),
+                length: event.length,
+                skipped,
+                audible: marker.range.len(),
+                gain: event.gain().to_bits(),
+                fade_in: event.arrangement.fade_in,
+                fade_out: event.arrangement.fade_out,
+            };
+            if let Some(&i) = index.get(&key) {
+                clips[i].markers.push(marker);
+                continue;
+            }
 
-        // Вычисляем длительность
-        let duration_ms = arrangement_end_ms - arrangement_start_ms;
-        let duration_samples = ((duration_ms as f64 / 1000.0) * self.sample_rate as f64) as usize;
+            // Событие, сдвинутое так, что буфер начинается с первого звучащего сэмпла
+            let shifted = PlacedEvent {
+                start: -skipped,
+                ..event
+            };
+            let mut samples = vec![0.0f32; marker.range.len()];
+            self.apply_arrangement_to_buffer(&mut samples, source_samples, &shifted, &timeline);
+            if let Some(factor) = normalize_factor {
+                samples.iter_mut().for_each(|sample| *sample *= factor);
+            }
 
-        log::info!(
-            "Arrangement: offset_ms={offset_ms}, duration_ms={duration_ms}, offset_samples={offset_samples}, duration_samples={duration_samples}"
-        );
+            index.insert(key, clips.len());
+            clips.push(RenderedClip {
+                samples,
+                markers: vec![marker],
+                sample_rate: self.sample_rate,
+            });
+        }
 
-        // Получаем обрезанную часть источника
-        let cut_start_samples = (source.cut.start * self.sample_rate as f64) as usize;
-        let cut_end_samples = (source.cut.end * self.sample_rate as f64) as usize;
-        let cut_end_samples = cut_end_samples.min(source_samples.len());
+        for clip in &mut clips {
+            clip.markers.sort_by_key(|marker| marker.range.start);
+        }
+        clips.sort_by_key(|clip| clip.markers[0].range.start);
+        Ok(clips)
+    }
 
-        log::info!(
-            "Source cut: start={:.2}s, end={:.2}s -> samples {}..{} (source len: {})",
-            source.cut.start,
-            source.cut.end,
-            cut_start_samples,
-            cut_end_samples,
-            source_samples.len()
-        );
+    /// Пиковая амплитуда смеси всей записи без её буфера: вне событий запись
+    /// молчит, поэтому достаточно смешать каждую группу перекрывающихся событий
+    fn mix_peak(
+        &self,
+        placed: &[(PlacedEvent, &Source, &[f32], Marker)],
+        timeline: &Timeline,
+        cancel: &CancelToken,
+    ) -> Result<f32> {
+        let mut order: Vec<usize> = (0..placed.len()).collect();
+        order.sort_by_key(|&i| placed[i].3.range.start);
+
+        let mut peak = 0.0f32;
+        let mut group_start = 0;
+        while group_start < order.len() {
+            cancel.check()?;
+            let start = placed[order[group_start]].3.range.start;
+            let mut end = placed[order[group_start]].3.range.end;
+            let mut group_end = group_start + 1;
+            while group_end < order.len() && placed[order[group_end]].3.range.start < end {
+                end = end.max(placed[order[group_end]].3.range.end);
+                group_end += 1;
+            }
 
-        if cut_start_samples >= cut_end_samples || cut_start_samples >= source_samples.len() {
-            log::warn!("Invalid cut range, skipping arrangement");
-            return Ok(());
+            let mut buffer = vec![0.0f32; end - start];
+            for &i in &order[group_start..group_end] {
+                let (event, _, source_samples, _) = &placed[i];
+                let shifted = PlacedEvent {
+                    start: event.start - start as SamplePos,
+                    ..event.clone()
+                };
+                self.apply_arrangement_to_buffer(&mut buffer, source_samples, &shifted, timeline);
+            }
+            peak = buffer.iter().map(|&s| s.abs()).fold(peak, f32::max);
+            group_start = group_end;
         }
+        Ok(peak)
+    }
+
+    /// Находит источник объявления и размещает его на шкале.
+    /// `None`, если источник не загружен или его обрезка пуста.
+    fn place_arrangement<'a>(
+        &self,
+        timeline: &Timeline,
+        arrangement: &'a Arrangement,
+        sources: &'a [Source],
+        audio_cache: &'a SourceCache,
+    ) -> Result<Option<(PlacedEvent<'a>, &'a Source, &'a [f32])>> {
+        // Находим соответствующий источник
+        let source = sources
+            .iter()
+            .find(|s| s.type_id == arrangement.type_id)
+            .context("Source not found for arrangement")?;
 
-        let source_cut = &source_samples[cut_start_samples..cut_end_samples];
-        let samples_to_copy = duration_samples.min(source_cut.len());
+        let Some(source_samples) = audio_cache.get(&source.id) else {
+            return Ok(None);
+        };
+        match timeline.place(arrangement, source, source_samples.len()) {
+            Some(event) => Ok(Some((event, source, source_samples))),
+            None => {
+                log::warn!(
+                    "Empty cut range or slot for source {}, skipping arrangement {}",
+                    source.title,
+                    arrangement.id
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Применяет объявление к финальному буферу
+    fn apply_arrangement_to_buffer(
+        &self,
+        final_buffer: &mut [f32],
+        source_samples: &[f32],
+        event: &PlacedEvent,
+        timeline: &Timeline,
+    ) {
+        let arrangement = event.arrangement;
+        let source_cut = &source_samples[event.cut.clone()];
 
         log::info!(
-            "Copying {} samples from source_cut (len: {}) to buffer at offset {}",
-            samples_to_copy,
-            source_cut.len(),
-            offset_samples
+            "Arrangement {}: start={} samples, length={} samples, cut={}..{} (source len: {})",
+            arrangement.id,
+            event.start,
+            event.length,
+            event.cut.start,
+            event.cut.end,
+            source_samples.len()
         );
 
         // Применяем громкость
-        let loudness = arrangement.loudness.unwrap_or(100.0) / 100.0;
+        let loudness = event.gain();
 
         // Fade effects
-        let fade_duration_samples = (0.3 * self.sample_rate as f64) as usize; // 300ms fade
+        let fade_duration_samples = timeline.seconds_to_samples(FADE_SECONDS);
+        let buffer_len = final_buffer.len() as SamplePos;
+
+        // Часть события до начала записи отбрасывается
+        let first = (-event.start).clamp(0, event.length);
 
         // Копируем данные с зацикливанием если необходимо
-        for i in 0..duration_samples {
-            let target_index = offset_samples + i;
-            if target_index >= final_buffer.len() {
+        for i in first..event.length {
+            let target_index = event.start + i;
+            if target_index >= buffer_len {
                 break;
             }
 
             // Зацикливаем источник если он короче нужной длительности
-            let source_index = i % source_cut.len();
+            let source_index = i as usize % source_cut.len();
             let mut sample = source_cut[source_index] * loudness;
 
             // Apply fade in
@@ -411,17 +655,15 @@ impl AudioProcessor {
             }
 
             // Apply fade out
-            if arrangement.fade_out && i >= duration_samples - fade_duration_samples {
-                let remaining = duration_samples - i;
+            let remaining = event.length - i;
+            if arrangement.fade_out && remaining <= fade_duration_samples {
                 let fade_factor = remaining as f32 / fade_duration_samples as f32;
                 sample *= fade_factor;
             }
 
             // Mix with existing audio
-            final_buffer[target_index] += sample;
+            final_buffer[target_index as usize] += sample;
         }
-
-        Ok(())
     }
 
     /// Нормализует аудио для предотвращения клиппинга
@@ -430,20 +672,27 @@ impl AudioProcessor {
 
         log::info!("Нормализация аудио: макс. амплитуда {max_amplitude:.3}");
 
-        if max_amplitude > 0.95 {
-            let normalize_factor = 0.9 / max_amplitude;
-            log::info!("Применяем нормализацию с коэффициентом {normalize_factor:.3}");
+        if let Some(normalize_factor) = normalization_factor(max_amplitude) {
             for sample in buffer.iter_mut() {
                 *sample *= normalize_factor;
             }
-        } else {
-            log::info!("Нормализация не требуется");
         }
     }
 
-    /// Сохраняет аудио в WAV файл
-    #[allow(dead_code)]
+    /// Сохраняет аудио в WAV файл (RF64, если запись не помещается в 4 ГБ)
     pub fn save_as_wav(&self, samples: &[f32], output_path: &str) -> Result<()> {
+        let mut quantizer = Quantizer::new(16, Dither::Tpdf);
+
+        if wav::needs_rf64(samples.len(), 2, 0) {
+            log::warn!("WAV больше 4 ГБ, сохраняем в формате RF64: {output_path}");
+            let mut writer =
+                Rf64Writer::create(Path::new(output_path), self.sample_rate, 16, false)?;
+            for &sample in samples {
+                writer.write_int(quantizer.quantize(sample))?;
+            }
+            return writer.finalize();
+        }
+
         let spec = WavSpec {
             channels: 1,
             sample_rate: self.sample_rate,
@@ -458,9 +707,8 @@ impl AudioProcessor {
             .with_context(|| "Failed to create WAV writer")?;
 
         for &sample in samples {
-            let sample_i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
             writer
-                .write_sample(sample_i16)
+                .write_sample(quantizer.quantize(sample) as i16)
                 .context("Failed to write sample")?;
         }
 
@@ -469,3 +717,149 @@ impl AudioProcessor {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    const RATE: usize = 44100;
+
+    fn origin() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 5, 8, 0, 0).unwrap()
+    }
+
+    fn record(seconds: i64) -> TimeOfRecord {
+        TimeOfRecord {
+            start: origin(),
+            end: origin() + Duration::seconds(seconds),
+        }
+    }
+
+    fn arrangement(id: &str, type_id: &str, start_ms: i64, end_ms: i64) -> Arrangement {
+        Arrangement {
+            id: id.into(),
+            type_id: Some(type_id.into()),
+            playing_time: PlayingTime {
+                start: origin() + Duration::milliseconds(start_ms),
+                end: origin() + Duration::milliseconds(end_ms),
+            },
+            loudness: None,
+            fade_in: false,
+            fade_out: false,
+            fixed_time: None,
+            relative_to: None,
+        }
+    }
+
+    fn source(id: &str) -> Source {
+        Source {
+            id: id.into(),
+            title: id.into(),
+            type_id: Some(id.into()),
+            file_path: String::new(),
+            cut: Cut {
+                start: 0.0,
+                end: 1.0,
+            },
+        }
+    }
+
+    /// Источники длиной в секунду с постоянной амплитудой
+    fn cache(levels: &[(&str, f32)]) -> SourceCache {
+        levels
+            .iter()
+            .map(|&(id, level)| (id.to_string(), vec![level; RATE]))
+            .collect()
+    }
+
+    fn render(
+        arrangements: &[Arrangement],
+        sources: &[Source],
+        cache: &SourceCache,
+    ) -> (RenderedRecord, Vec<RenderedClip>) {
+        let processor = AudioProcessor::new();
+        let record = record(10);
+        let cancel = CancelToken::new();
+        let full = processor
+            .render_record("r", arrangements, &record, sources, cache, &cancel, |_| {})
+            .unwrap();
+        let clips = processor
+            .render_clips(arrangements, &record, sources, cache, &cancel)
+            .unwrap();
+        (full, clips)
+    }
+
+    #[test]
+    fn equal_events_share_one_clip() {
+        let sources = [source("a"), source("b")];
+        let cache = cache(&[("a", 0.5), ("b", 0.5)]);
+        let mut louder = arrangement("3", "a", 5_000, 6_000);
+        louder.loudness = Some(50.0);
+        let arrangements = [
+            arrangement("1", "a", 1_000, 2_000),
+            arrangement("2", "a", 3_000, 4_000),
+            louder,
+            arrangement("4", "b", 7_000, 8_000),
+            // Обрезано концом записи — звучит иначе, чем целое событие
+            arrangement("5", "a", 9_500, 10_500),
+        ];
+        let (_, clips) = render(&arrangements, &sources, &cache);
+
+        let ids: Vec<Vec<&str>> = clips
+            .iter()
+            .map(|clip| {
+                clip.markers
+                    .iter()
+                    .map(|m| m.arrangement_id.as_str())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(ids, vec![vec!["1", "2"], vec!["3"], vec!["4"], vec!["5"]]);
+        assert_eq!(clips[3].samples.len(), RATE / 2);
+    }
+
+    #[test]
+    fn clips_match_full_render() {
+        let sources = [source("a"), source("b")];
+        // Громкий источник требует нормализации всей записи
+        let cache = cache(&[("a", 0.5), ("b", 1.0)]);
+        let arrangements = [
+            arrangement("1", "a", 1_000, 2_000),
+            arrangement("2", "b", 3_000, 4_000),
+            arrangement("3", "a", 5_000, 6_000),
+        ];
+        let (full, clips) = render(&arrangements, &sources, &cache);
+
+        let mut markers: Vec<Marker> = clips.iter().flat_map(|c| c.markers.clone()).collect();
+        markers.sort_by_key(|m| m.range.start);
+        assert_eq!(markers, full.markers);
+
+        for clip in &clips {
+            for marker in &clip.markers {
+                assert_eq!(clip.samples.len(), marker.range.len());
+                assert_eq!(clip.samples[..], full.samples[marker.range.clone()]);
+            }
+        }
+        // Тихий фрагмент ослаблен так же, как в полной записи
+        assert!((clips[0].samples[0] - 0.45).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clip_gain_uses_peak_of_overlapping_mix() {
+        let sources = [source("a"), source("b")];
+        let cache = cache(&[("a", 0.6), ("b", 0.6)]);
+        let arrangements = [
+            arrangement("1", "a", 1_000, 2_000),
+            // Перекрывается с первым: пик смеси 1.2, хотя каждый фрагмент тише 0.95
+            arrangement("2", "b", 1_500, 2_500),
+        ];
+        let (full, clips) = render(&arrangements, &sources, &cache);
+
+        let factor = 0.9 / 1.2;
+        assert!((full.samples[RATE + RATE / 4] - 0.6 * factor).abs() < 1e-6);
+        for clip in &clips {
+            assert!((clip.samples[0] - 0.6 * factor).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Duration, NaiveTime};
+
+use crate::audio::schedule::clip_duration;
+use crate::audio::types::*;
+use crate::audio::zone::ProjectZone;
+
+/// Разворачивает правило повторения в объявления, попадающие в интервал записи
+pub fn expand_rule(
+    rule: &RecurrenceRule,
+    record_name: &str,
+    time_record: &TimeOfRecord,
+    zone: &ProjectZone,
+    sources: &[Source],
+) -> Result<Vec<Arrangement>> {
+    if !rule.records.is_empty() && !rule.records.iter().any(|r| r == record_name) {
+        return Ok(Vec::new());
+    }
+
+    let duration = match rule.duration_ms {
+        Some(ms) => Duration::milliseconds(ms.max(0)),
+        None => clip_duration(sources, &rule.type_id).with_context(|| {
+            format!(
+                "Правило {}: не найден источник для определения длительности",
+                rule.id
+            )
+        })?,
+    };
+    let times = rule_times(rule)?;
+
+    let mut arrangements = Vec::new();
+    // Ночное окно предыдущих суток может заходить в начало записи
+    let mut date = zone
+        .local_date(&time_record.start)
+        .pred_opt()
+        .context("Дата вне допустимого диапазона")?;
+    while date <= zone.local_date(&time_record.end) {
+        let weekday_matches = rule.weekdays.is_empty() || rule.weekdays.contains(&date.weekday());
+        if weekday_matches && !rule.except_dates.contains(&date) {
+            for (day, time) in &times {
+                let wall_time = (date + Duration::days(*day)).and_time(*time);
+                let start = zone.resolve(wall_time)?;
+                if start < time_record.start || start >= time_record.end {
+                    continue;
+                }
+
+                arrangements.push(Arrangement {
+                    id: format!("{}-{}", rule.id, wall_time.format("%Y%m%d-%H%M%S")),
+                    type_id: rule.type_id.clone(),
+                    playing_time: PlayingTime {
+                        start,
+                        end: start + duration,
+                    },
+                    loudness: rule.loudness,
+                    fade_in: rule.fade_in,
+                    fade_out: rule.fade_out,
+                    fixed_time: rule.fixed_time,
+                    relative_to: None,
+                });
+            }
+        }
+        date = date.succ_opt().context("Дата вне допустимого диапазона")?;
+    }
+
+    log::info!(
+        "Правило {} для записи '{}': {} объявлений",
+        rule.id,
+        record_name,
+        arrangements.len()
+    );
+    Ok(arrangements)
+}
+
+/// Время срабатывания правила без исключений: смещение в сутках от дня правила и время.
+/// Окно `every` с `from` позже `to` переходит через полночь в следующие сутки
+fn rule_times(rule: &RecurrenceRule) -> Result<Vec<(i64, NaiveTime)>> {
+    let mut times = match &rule.pattern {
+        RecurrencePattern::Every {
+            interval_minutes,
+            from,
+            to,
+        } => {
+            if *interval_minutes == 0 {
+                bail!(
+                    "Правило {}: интервал повторения должен быть больше нуля",
+                    rule.id
+                );
+            }
+
+            let step = Duration::minutes(*interval_minutes as i64);
+            let last = (i64::from(to < from), *to);
+            let mut times = Vec::new();
+            let mut slot = (0, *from);
+            while slot <= last {
+                times.push(slot);
+                let (next, wrapped) = slot.1.overflowing_add_signed(step);
+                slot = (slot.0 + wrapped / 86_400, next);
+            }
+            times
+        }
+        RecurrencePattern::At { times } => times.iter().map(|t| (0, *t)).collect(),
+    };
+
+    times.retain(|(_, t)| !rule.except_times.contains(t));
+    times.sort();
+    times.dedup();
+    Ok(times)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn rule(pattern: RecurrencePattern) -> RecurrenceRule {
+        RecurrenceRule {
+            id: "r".into(),
+            type_id: None,
+            pattern,
+            records: Vec::new(),
+            weekdays: Vec::new(),
+            except_times: Vec::new(),
+            except_dates: Vec::new(),
+            duration_ms: Some(1000),
+            loudness: None,
+            fade_in: false,
+            fade_out: false,
+            fixed_time: None,
+        }
+    }
+
+    #[test]
+    fn every_within_day() {
+        let mut rule = rule(RecurrencePattern::Every {
+            interval_minutes: 20,
+            from: time(8, 0),
+            to: time(9, 0),
+        });
+        rule.except_times = vec![time(8, 40)];
+        let times = rule_times(&rule).unwrap();
+        assert_eq!(
+            times,
+            vec![(0, time(8, 0)), (0, time(8, 20)), (0, time(9, 0))]
+        );
+    }
+
+    #[test]
+    fn overnight_window_wraps_past_midnight() {
+        let rule = rule(RecurrencePattern::Every {
+            interval_minutes: 30,
+            from: time(23, 0),
+            to: time(1, 0),
+        });
+        let times = rule_times(&rule).unwrap();
+        assert_eq!(
+            times,
+            vec![
+                (0, time(23, 0)),
+                (0, time(23, 30)),
+                (1, time(0, 0)),
+                (1, time(0, 30)),
+                (1, time(1, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_interval_is_rejected() {
+        let rule = rule(RecurrencePattern::Every {
+            interval_minutes: 0,
+            from: time(8, 0),
+            to: time(9, 0),
+        });
+        assert!(rule_times(&rule).is_err());
+    }
+
+    #[test]
+    fn overnight_window_reaches_next_record() {
+        let rule = rule(RecurrencePattern::Every {
+            interval_minutes: 60,
+            from: time(22, 0),
+            to: time(2, 0),
+        });
+        let record = TimeOfRecord {
+            start: Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 3, 5, 6, 0, 0).unwrap(),
+        };
+        let zone = ProjectZone::from_name(Some("UTC")).unwrap();
+        let starts: Vec<_> = expand_rule(&rule, "night", &record, &zone, &[])
+            .unwrap()
+            .into_iter()
+            .map(|a| a.playing_time.start)
+            .collect();
+        let expected: Vec<_> = (0..=2)
+            .map(|h| Utc.with_ymd_and_hms(2024, 3, 5, h, 0, 0).unwrap())
+            .collect();
+        assert_eq!(starts, expected);
+    }
+}
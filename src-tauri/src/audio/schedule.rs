@@ -0,0 +1,254 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+use crate::audio::types::*;
+use crate::audio::zone::ProjectZone;
+use crate::audio::{recurrence, timetable};
+
+/// Возвращает объявления записи с разрешённым абсолютным временем звучания:
+/// явные объявления, развёрнутые правила повторения и расписания уроков,
+/// цепочки `relativeTo`
+pub fn resolve_record(request: &ExportRequest, record_name: &str) -> Result<Vec<Arrangement>> {
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Record not found")?;
+    let zone = ProjectZone::from_name(request.time_zone.as_deref())?;
+
+    if zone.crosses_transition(&time_record.start, &time_record.end) {
+        log::info!(
+            "Запись '{record_name}' пересекает перевод часов, раскладка по реальному времени"
+        );
+    }
+
+    let mut arrangements = request
+        .arrangements
+        .get(record_name)
+        .cloned()
+        .unwrap_or_default();
+    for rule in &request.recurrences {
+        arrangements.extend(recurrence::expand_rule(
+            rule,
+            record_name,
+            time_record,
+            &zone,
+            &request.sources,
+        )?);
+    }
+    for timetable in &request.timetables {
+        arrangements.extend(timetable::expand_timetable(
+            timetable,
+            record_name,
+            time_record,
+            &zone,
+            &request.sources,
+        )?);
+    }
+
+    resolve_chains(&arrangements, &request.sources)
+        .with_context(|| format!("Не удалось разрешить расписание записи {record_name}"))
+}
+
+/// Длительность обрезанного источника, соответствующего типу объявления
+pub fn clip_duration(sources: &[Source], type_id: &Option<String>) -> Option<Duration> {
+    sources
+        .iter()
+        .find(|s| &s.type_id == type_id)
+        .map(|s| Duration::milliseconds(((s.cut.end - s.cut.start) * 1000.0).round() as i64))
+}
+
+/// Переводит объявления с `relativeTo` в абсолютное время.
+///
+/// Зависимое объявление сохраняет свою длительность и сдвигается так, чтобы
+/// начаться через `offsetMs` после начала или конца опорного объявления.
+/// Для опорных объявлений с `fixedTime` край берётся по фактическому звучанию
+/// источника, поэтому цепочка не ломается при замене первого клипа.
+pub fn resolve_chains(
+    arrangements: &[Arrangement],
+    sources: &[Source],
+) -> Result<Vec<Arrangement>> {
+    let mut resolver = ChainResolver {
+        arrangements,
+        sources,
+        index: arrangements
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (a.id.as_str(), i))
+            .collect(),
+        resolved: vec![None; arrangements.len()],
+        visiting: Vec::new(),
+    };
+
+    let mut result = Vec::with_capacity(arrangements.len());
+    for (i, arrangement) in arrangements.iter().enumerate() {
+        let playing_time = resolver.resolve(i)?;
+        result.push(Arrangement {
+            playing_time,
+            ..arrangement.clone()
+        });
+    }
+    Ok(result)
+}
+
+struct ChainResolver<'a> {
+    arrangements: &'a [Arrangement],
+    sources: &'a [Source],
+    index: HashMap<&'a str, usize>,
+    resolved: Vec<Option<PlayingTime>>,
+    /// Стек объявлений, разрешаемых в данный момент (для поиска циклов)
+    visiting: Vec<usize>,
+}
+
+impl ChainResolver<'_> {
+    fn resolve(&mut self, i: usize) -> Result<PlayingTime> {
+        if let Some(playing_time) = &self.resolved[i] {
+            return Ok(playing_time.clone());
+        }
+
+        let arrangement = &self.arrangements[i];
+        let Some(relative) = &arrangement.relative_to else {
+            self.resolved[i] = Some(arrangement.playing_time.clone());
+            return Ok(arrangement.playing_time.clone());
+        };
+
+        if let Some(pos) = self.visiting.iter().position(|&v| v == i) {
+            let cycle: Vec<&str> = self.visiting[pos..]
+                .iter()
+                .chain(std::iter::once(&i))
+                .map(|&v| self.arrangements[v].id.as_str())
+                .collect();
+            bail!("Циклическая зависимость объявлений: {}", cycle.join(" → "));
+        }
+
+        let anchor_index = *self
+            .index
+            .get(relative.arrangement_id.as_str())
+            .with_context(|| {
+                format!(
+                    "Объявление {} ссылается на несуществующее объявление {}",
+                    arrangement.id, relative.arrangement_id
+                )
+            })?;
+
+        self.visiting.push(i);
+        let anchor_slot = self.resolve(anchor_index)?;
+        self.visiting.pop();
+
+        let (anchor_start, anchor_end) =
+            self.played_span(&self.arrangements[anchor_index], &anchor_slot);
+        let edge = match relative.edge {
+            AnchorEdge::Start => anchor_start,
+            AnchorEdge::End => anchor_end,
+        };
+
+        let duration =
+            (arrangement.playing_time.end - arrangement.playing_time.start).max(Duration::zero());
+        let start = edge + Duration::milliseconds(relative.offset_ms);
+        let playing_time = PlayingTime {
+            start,
+            end: start + duration,
+        };
+
+        self.resolved[i] = Some(playing_time.clone());
+        Ok(playing_time)
+    }
+
+    /// Фактический интервал звучания объявления с учётом `fixedTime`: как и
+    /// при рендеринге, источник не выходит за границы слота
+    fn played_span(
+        &self,
+        arrangement: &Arrangement,
+        slot: &PlayingTime,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        let slot_len = (slot.end - slot.start).max(Duration::zero());
+        let clip = clip_duration(self.sources, &arrangement.type_id).map(|clip| clip.min(slot_len));
+
+        match (arrangement.fixed_time, clip) {
+            (Some(FixedTime::Start), Some(clip)) => (slot.start, slot.start + clip),
+            (Some(FixedTime::End), Some(clip)) => (slot.end - clip, slot.end),
+            (Some(FixedTime::Center), Some(clip)) => {
+                let start = slot.start + (slot.end - slot.start - clip) / 2;
+                (start, start + clip)
+            }
+            _ => (slot.start, slot.end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn origin() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 5, 8, 0, 0).unwrap()
+    }
+
+    fn arrangement(id: &str, start_s: i64, anchor: Option<(&str, AnchorEdge)>) -> Arrangement {
+        Arrangement {
+            id: id.into(),
+            type_id: None,
+            playing_time: PlayingTime {
+                start: origin() + Duration::seconds(start_s),
+                end: origin() + Duration::seconds(start_s + 10),
+            },
+            loudness: None,
+            fade_in: false,
+            fade_out: false,
+            fixed_time: None,
+            relative_to: anchor.map(|(id, edge)| RelativeTime {
+                arrangement_id: id.into(),
+                edge,
+                offset_ms: 500,
+            }),
+        }
+    }
+
+    #[test]
+    fn chain_follows_anchor_end() {
+        let arrangements = [
+            arrangement("c", 0, Some(("b", AnchorEdge::End))),
+            arrangement("b", 0, Some(("a", AnchorEdge::Start))),
+            arrangement("a", 60, None),
+        ];
+        let resolved = resolve_chains(&arrangements, &[]).unwrap();
+        let starts: Vec<_> = resolved
+            .iter()
+            .map(|a| a.playing_time.start - origin())
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                Duration::milliseconds(71_000),
+                Duration::milliseconds(60_500),
+                Duration::seconds(60),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycle_is_reported_with_its_members() {
+        let arrangements = [
+            arrangement("a", 0, None),
+            arrangement("b", 0, Some(("d", AnchorEdge::End))),
+            arrangement("c", 0, Some(("b", AnchorEdge::End))),
+            arrangement("d", 0, Some(("c", AnchorEdge::Start))),
+        ];
+        let error = resolve_chains(&arrangements, &[]).unwrap_err().to_string();
+        assert!(error.contains("b → d → c → b"), "{error}");
+    }
+
+    #[test]
+    fn self_reference_is_a_cycle() {
+        let arrangements = [arrangement("a", 0, Some(("a", AnchorEdge::Start)))];
+        assert!(resolve_chains(&arrangements, &[]).is_err());
+    }
+
+    #[test]
+    fn missing_anchor_is_an_error() {
+        let arrangements = [arrangement("a", 0, Some(("x", AnchorEdge::Start)))];
+        let error = resolve_chains(&arrangements, &[]).unwrap_err().to_string();
+        assert!(error.contains("несуществующее"), "{error}");
+    }
+}
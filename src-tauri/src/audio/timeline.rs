@@ -0,0 +1,303 @@
+use chrono::{DateTime, Utc};
+use std::ops::Range;
+
+use crate::audio::types::*;
+
+/// Позиция на временной шкале записи в сэмплах (может быть отрицательной,
+/// если событие начинается раньше записи)
+pub type SamplePos = i64;
+
+/// Целочисленная временная шкала записи.
+///
+/// Все `PlayingTime` и `Cut` переводятся в сэмплы один раз и с одинаковым
+/// округлением (к ближайшему, половина — от нуля), поэтому повторный экспорт
+/// даёт побитово одинаковый результат, а соседние события стыкуются точно.
+/// Позиции отсчитываются по реально прошедшему времени, поэтому перевод
+/// часов внутри записи не сдвигает события (см. `ProjectZone`).
+#[derive(Debug, Clone, Copy)]
+pub struct Timeline {
+    sample_rate: u32,
+    origin_ms: i64,
+    length: SamplePos,
+}
+
+/// Объявление, разрешённое в позиции на временной шкале
+#[derive(Debug, Clone)]
+pub struct PlacedEvent<'a> {
+    pub arrangement: &'a Arrangement,
+    /// Первый сэмпл события относительно начала записи
+    pub start: SamplePos,
+    /// Длительность события в сэмплах
+    pub length: SamplePos,
+    /// Обрезанный фрагмент источника в сэмплах декодированного файла
+    pub cut: Range<usize>,
+}
+
+/// Объявление в отрендеренной записи (для глав, cue и разметки)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub arrangement_id: String,
+    pub type_id: Option<String>,
+    pub source_id: String,
+    /// Название источника
+    pub title: String,
+    /// Звучащий фрагмент в сэмплах записи (обрезан по её границам)
+    pub range: Range<usize>,
+    /// Первый звучащий сэмпл в декодированном файле источника
+    pub source_offset: usize,
+    /// Множитель громкости (`loudness` / 100)
+    pub gain: f32,
+    pub fade_in: bool,
+    pub fade_out: bool,
+}
+
+impl PlacedEvent<'_> {
+    /// Маркер события; `None`, если оно целиком вне записи
+    pub fn marker(&self, source: &Source, record_len: usize) -> Option<Marker> {
+        let start = self.start.clamp(0, record_len as SamplePos) as usize;
+        let end = (self.start + self.length).clamp(0, record_len as SamplePos) as usize;
+        let skipped = (start as SamplePos - self.start) as usize;
+        (start < end).then(|| Marker {
+            arrangement_id: self.arrangement.id.clone(),
+            type_id: self.arrangement.type_id.clone(),
+            source_id: source.id.clone(),
+            title: source.title.clone(),
+            range: start..end,
+            source_offset: self.cut.start + skipped % self.cut.len(),
+            gain: self.gain(),
+            fade_in: self.arrangement.fade_in,
+            fade_out: self.arrangement.fade_out,
+        })
+    }
+
+    /// Множитель громкости события
+    pub fn gain(&self) -> f32 {
+        self.arrangement.loudness.unwrap_or(100.0) / 100.0
+    }
+}
+
+impl Timeline {
+    pub fn new(time_record: &TimeOfRecord, sample_rate: u32) -> Self {
+        let origin_ms = time_record.start.timestamp_millis();
+        let end_ms = time_record.end.timestamp_millis();
+        let mut timeline = Self {
+            sample_rate,
+            origin_ms,
+            length: 0,
+        };
+        timeline.length = timeline.ms_to_samples(end_ms - origin_ms).max(0);
+        timeline
+    }
+
+    /// Длина записи в сэмплах
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+    /// Переводит миллисекунды в сэмплы с округлением к ближайшему
+    pub fn ms_to_samples(&self, ms: i64) -> SamplePos {
+        let scaled = ms as i128 * self.sample_rate as i128;
+        let rounded = (scaled.abs() + 500) / 1000;
+        (rounded * scaled.signum()) as SamplePos
+    }
+
+    /// Переводит секунды (например, из `Cut`) в сэмплы с округлением к ближайшему
+    pub fn seconds_to_samples(&self, seconds: f64) -> SamplePos {
+        (seconds * self.sample_rate as f64).round() as SamplePos
+    }
+
+    /// Позиция момента времени относительно начала записи
+    pub fn position(&self, time: &DateTime<Utc>) -> SamplePos {
+        self.ms_to_samples(time.timestamp_millis() - self.origin_ms)
+    }
+
+    /// Переводит сэмплы обратно в секунды (для отображения и логов)
+    pub fn samples_to_seconds(&self, samples: SamplePos) -> f64 {
+        samples as f64 / self.sample_rate as f64
+    }
+
+    /// Разрешает обрезку источника в диапазон сэмплов декодированного файла
+    pub fn cut_range(&self, cut: &Cut, source_len: usize) -> Option<Range<usize>> {
+        let start = self.seconds_to_samples(cut.start).max(0) as usize;
+        let end = (self.seconds_to_samples(cut.end).max(0) as usize).min(source_len);
+
+        if start >= end {
+            return None;
+        }
+        Some(start..end)
+    }
+
+    /// Размещает объявление на шкале. Возвращает `None`, если обрезка источника
+    /// или слот пусты.
+    ///
+    /// Без привязки (`fixedTime: null`) объявление заполняет весь слот: короткий
+    /// источник зацикливается, длинный обрезается. С привязкой источник звучит
+    /// один раз и выравнивается по началу, концу или центру слота; за границы
+    /// слота он не выходит: у длинного источника остаётся часть у привязанного
+    /// края (начало, конец или середина), остальное обрезается.
+    pub fn place<'a>(
+        &self,
+        arrangement: &'a Arrangement,
+        source: &Source,
+        source_len: usize,
+    ) -> Option<PlacedEvent<'a>> {
+        let cut = self.cut_range(&source.cut, source_len)?;
+        let slot_start = self.position(&arrangement.playing_time.start);
+        let slot_end = self.position(&arrangement.playing_time.end).max(slot_start);
+        let slot_len = slot_end - slot_start;
+        let clip_len = cut.len() as SamplePos;
+        let length = clip_len.min(slot_len);
+
+        // Сдвиг звучащей части внутри обрезки источника и начало на шкале
+        let (skip, start, length) = match arrangement.fixed_time {
+            None => (0, slot_start, slot_len),
+            Some(FixedTime::Start) => (0, slot_start, length),
+            Some(FixedTime::End) => (clip_len - length, slot_end - length, length),
+            Some(FixedTime::Center) => (
+                (clip_len - length) / 2,
+                slot_start + (slot_len - length).div_euclid(2),
+                length,
+            ),
+        };
+        if length == 0 {
+            return None;
+        }
+        let cut = match arrangement.fixed_time {
+            None => cut,
+            Some(_) => cut.start + skip as usize..cut.start + (skip + length) as usize,
+        };
+
+        Some(PlacedEvent {
+            arrangement,
+            start,
+            length,
+            cut,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn origin() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 5, 8, 0, 0).unwrap()
+    }
+
+    fn timeline(length_ms: i64) -> Timeline {
+        let record = TimeOfRecord {
+            start: origin(),
+            end: origin() + Duration::milliseconds(length_ms),
+        };
+        Timeline::new(&record, 44100)
+    }
+
+    fn arrangement(start_ms: i64, end_ms: i64, fixed_time: Option<FixedTime>) -> Arrangement {
+        Arrangement {
+            id: "a".into(),
+            type_id: None,
+            playing_time: PlayingTime {
+                start: origin() + Duration::milliseconds(start_ms),
+                end: origin() + Duration::milliseconds(end_ms),
+            },
+            loudness: None,
+            fade_in: false,
+            fade_out: false,
+            fixed_time,
+            relative_to: None,
+        }
+    }
+
+    fn source(start: f64, end: f64) -> Source {
+        Source {
+            id: "s".into(),
+            title: "s".into(),
+            type_id: None,
+            file_path: String::new(),
+            cut: Cut { start, end },
+        }
+    }
+
+    #[test]
+    fn ms_round_half_away_from_zero() {
+        let timeline = timeline(1000);
+        assert_eq!(timeline.ms_to_samples(1), 44);
+        assert_eq!(timeline.ms_to_samples(5), 221);
+        assert_eq!(timeline.ms_to_samples(-5), -221);
+        assert_eq!(timeline.ms_to_samples(1000), 44100);
+        assert_eq!(timeline.len(), 44100);
+    }
+
+    #[test]
+    fn adjacent_events_abut() {
+        let timeline = timeline(10_000);
+        let source = source(0.0, 5.0);
+        let first = arrangement(1_005, 2_005, None);
+        let second = arrangement(2_005, 3_005, None);
+        let first = timeline.place(&first, &source, 44100 * 5).unwrap();
+        let second = timeline.place(&second, &source, 44100 * 5).unwrap();
+        assert_eq!(first.start + first.length, second.start);
+    }
+
+    #[test]
+    fn event_before_record_has_negative_start() {
+        let timeline = timeline(10_000);
+        let early = arrangement(-5, 1_000, None);
+        let placed = timeline.place(&early, &source(0.0, 1.0), 44100).unwrap();
+        assert_eq!(placed.start, -221);
+    }
+
+    #[test]
+    fn cut_range_rounds_and_clamps() {
+        let timeline = timeline(1000);
+        let range = |start, end| timeline.cut_range(&Cut { start, end }, 44100);
+        assert_eq!(range(0.5, 2.0), Some(22050..44100));
+        assert_eq!(range(1.5, 2.0), None);
+        assert_eq!(range(-1.0, 0.00002), Some(0..1));
+    }
+
+    #[test]
+    fn center_rounds_towards_slot_start() {
+        let timeline = timeline(10_000);
+        let centered = arrangement(0, 1_000, Some(FixedTime::Center));
+        // 44100 − 22051 = 22049 сэмплов свободного места, слева остаётся меньшая половина
+        let placed = timeline
+            .place(&centered, &source(0.0, 22051.0 / 44100.0), 44100)
+            .unwrap();
+        assert_eq!(placed.start, 11024);
+        assert_eq!(placed.length, 22051);
+    }
+
+    #[test]
+    fn anchored_long_clip_stays_in_slot() {
+        let timeline = timeline(10_000);
+        // Слот 1 с, источник 3 с (0..132300 сэмплов)
+        let long = source(0.0, 3.0);
+        let place = |fixed_time| {
+            let slot = arrangement(2_000, 3_000, Some(fixed_time));
+            let placed = timeline.place(&slot, &long, 44100 * 3).unwrap();
+            (placed.start, placed.length, placed.cut)
+        };
+        assert_eq!(place(FixedTime::Start), (88200, 44100, 0..44100));
+        assert_eq!(place(FixedTime::End), (88200, 44100, 88200..132300));
+        assert_eq!(place(FixedTime::Center), (88200, 44100, 44100..88200));
+    }
+
+    #[test]
+    fn anchored_short_clip_plays_once() {
+        let timeline = timeline(10_000);
+        let short = source(0.0, 0.5);
+        let slot = arrangement(2_000, 3_000, Some(FixedTime::End));
+        let placed = timeline.place(&slot, &short, 44100).unwrap();
+        assert_eq!((placed.start, placed.length), (110250, 22050));
+        assert_eq!(placed.cut, 0..22050);
+    }
+
+    #[test]
+    fn empty_slot_is_not_placed() {
+        let timeline = timeline(10_000);
+        let slot = arrangement(2_000, 2_000, Some(FixedTime::Start));
+        assert!(timeline.place(&slot, &source(0.0, 1.0), 44100).is_none());
+    }
+}
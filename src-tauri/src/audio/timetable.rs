@@ -0,0 +1,300 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::audio::schedule::clip_duration;
+use crate::audio::types::*;
+use crate::audio::zone::ProjectZone;
+
+/// Урок, рассчитанный по расписанию
+struct Lesson {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+/// Разворачивает расписание уроков в звонки для записи.
+///
+/// Звонок на урок начинается ровно в начале урока, звонок с урока
+/// заканчивается ровно в конце урока (`fixedTime: "end"`). Как и у правил
+/// повторения, в запись попадают только звонки, чей урок начинается
+/// (заканчивается) внутри её интервала.
+pub fn expand_timetable(
+    timetable: &Timetable,
+    record_name: &str,
+    time_record: &TimeOfRecord,
+    zone: &ProjectZone,
+    sources: &[Source],
+) -> Result<Vec<Arrangement>> {
+    let Some(assignment) = timetable.records.iter().find(|r| r.record == record_name) else {
+        return Ok(Vec::new());
+    };
+
+    let variant = match &assignment.variant {
+        Some(name) => Some(
+            timetable
+                .variants
+                .iter()
+                .find(|v| &v.name == name)
+                .with_context(|| {
+                    format!("Расписание {}: вариант {} не найден", timetable.id, name)
+                })?,
+        ),
+        None => None,
+    };
+
+    let lessons = lessons(timetable, variant, zone.local_date(&time_record.start))?;
+    let mut arrangements = Vec::new();
+
+    for (i, lesson) in lessons.iter().enumerate() {
+        let number = i + 1;
+        if let Some(type_id) = &timetable.lesson_start_type_id {
+            let start = zone.resolve(lesson.start)?;
+            let clip = bell_duration(timetable, sources, type_id)?;
+            if time_record.start <= start && start < time_record.end {
+                arrangements.push(bell(
+                    timetable,
+                    format!("{}-lesson-{number}-start", timetable.id),
+                    type_id,
+                    PlayingTime {
+                        start,
+                        end: start + clip,
+                    },
+                    FixedTime::Start,
+                ));
+            }
+        }
+        if let Some(type_id) = &timetable.lesson_end_type_id {
+            let end = zone.resolve(lesson.end)?;
+            let clip = bell_duration(timetable, sources, type_id)?;
+            // Звонок с урока привязан к концу: урок, закончившийся ровно к
+            // началу записи, в неё не попадает, а закончившийся к её концу — да
+            if time_record.start < end && end <= time_record.end {
+                arrangements.push(bell(
+                    timetable,
+                    format!("{}-lesson-{number}-end", timetable.id),
+                    type_id,
+                    PlayingTime {
+                        start: end - clip,
+                        end,
+                    },
+                    FixedTime::End,
+                ));
+            }
+        }
+    }
+
+    log::info!(
+        "Расписание {} для записи '{}': {} уроков, {} звонков",
+        timetable.id,
+        record_name,
+        lessons.len(),
+        arrangements.len()
+    );
+    Ok(arrangements)
+}
+
+/// Рассчитывает начало и конец уроков в день записи с учётом варианта
+fn lessons(
+    timetable: &Timetable,
+    variant: Option<&TimetableVariant>,
+    date: NaiveDate,
+) -> Result<Vec<Lesson>> {
+    let breaks = variant
+        .and_then(|v| v.break_minutes.as_ref())
+        .unwrap_or(&timetable.break_minutes);
+    if breaks.len() + 1 < timetable.lesson_minutes.len() {
+        bail!(
+            "Расписание {}: не задана перемена после урока {}",
+            timetable.id,
+            breaks.len() + 1
+        );
+    }
+
+    let mut start = date.and_time(timetable.first_lesson_start);
+    let mut lessons = Vec::with_capacity(timetable.lesson_minutes.len());
+
+    for (i, &minutes) in timetable.lesson_minutes.iter().enumerate() {
+        let minutes = match variant {
+            Some(variant) => minutes.min(variant.lesson_minutes),
+            None => minutes,
+        };
+        let end = start + Duration::minutes(minutes as i64);
+        lessons.push(Lesson { start, end });
+
+        if let Some(&break_minutes) = breaks.get(i) {
+            start = end + Duration::minutes(break_minutes as i64);
+        }
+    }
+    Ok(lessons)
+}
+
+fn bell_duration(timetable: &Timetable, sources: &[Source], type_id: &str) -> Result<Duration> {
+    clip_duration(sources, &Some(type_id.to_string())).with_context(|| {
+        format!(
+            "Расписание {}: не найден источник звонка {}",
+            timetable.id, type_id
+        )
+    })
+}
+
+fn bell(
+    timetable: &Timetable,
+    id: String,
+    type_id: &str,
+    playing_time: PlayingTime,
+    fixed_time: FixedTime,
+) -> Arrangement {
+    Arrangement {
+        id,
+        type_id: Some(type_id.to_string()),
+        playing_time,
+        loudness: timetable.loudness,
+        fade_in: timetable.fade_in,
+        fade_out: timetable.fade_out,
+        fixed_time: Some(fixed_time),
+        relative_to: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn utc(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 5, h, m, 0).unwrap()
+    }
+
+    fn timetable() -> Timetable {
+        Timetable {
+            id: "t".into(),
+            first_lesson_start: time(8, 0),
+            lesson_minutes: vec![45, 45, 45],
+            break_minutes: vec![10, 20],
+            lesson_start_type_id: Some("in".into()),
+            lesson_end_type_id: Some("out".into()),
+            loudness: None,
+            fade_in: false,
+            fade_out: false,
+            variants: vec![TimetableVariant {
+                name: "short".into(),
+                lesson_minutes: 30,
+                break_minutes: None,
+            }],
+            records: vec![TimetableRecord {
+                record: "day".into(),
+                variant: None,
+            }],
+        }
+    }
+
+    fn sources() -> Vec<Source> {
+        ["in", "out"]
+            .into_iter()
+            .map(|type_id| Source {
+                id: type_id.into(),
+                title: type_id.into(),
+                type_id: Some(type_id.into()),
+                file_path: String::new(),
+                cut: Cut {
+                    start: 0.0,
+                    end: 5.0,
+                },
+            })
+            .collect()
+    }
+
+    fn expand(
+        timetable: &Timetable,
+        start: (u32, u32),
+        end: (u32, u32),
+    ) -> Result<Vec<Arrangement>> {
+        let record = TimeOfRecord {
+            start: utc(start.0, start.1),
+            end: utc(end.0, end.1),
+        };
+        let zone = ProjectZone::from_name(Some("UTC")).unwrap();
+        expand_timetable(timetable, "day", &record, &zone, &sources())
+    }
+
+    /// (id, начало, конец) звонков
+    fn bells(arrangements: &[Arrangement]) -> Vec<(String, String, String)> {
+        arrangements
+            .iter()
+            .map(|a| {
+                (
+                    a.id.clone(),
+                    a.playing_time.start.format("%H:%M:%S").to_string(),
+                    a.playing_time.end.format("%H:%M:%S").to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lessons_and_breaks() {
+        let bells = bells(&expand(&timetable(), (7, 0), (12, 0)).unwrap());
+        let expected = [
+            ("t-lesson-1-start", "08:00:00", "08:00:05"),
+            ("t-lesson-1-end", "08:44:55", "08:45:00"),
+            ("t-lesson-2-start", "08:55:00", "08:55:05"),
+            ("t-lesson-2-end", "09:39:55", "09:40:00"),
+            ("t-lesson-3-start", "10:00:00", "10:00:05"),
+            ("t-lesson-3-end", "10:44:55", "10:45:00"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(id, start, end)| (id.to_string(), start.to_string(), end.to_string()))
+            .collect();
+        assert_eq!(bells, expected);
+    }
+
+    #[test]
+    fn shortened_day_shrinks_lessons() {
+        let mut timetable = timetable();
+        timetable.records[0].variant = Some("short".into());
+        let ends: Vec<_> = bells(&expand(&timetable, (7, 0), (12, 0)).unwrap())
+            .into_iter()
+            .filter(|(id, _, _)| id.ends_with("-end"))
+            .map(|(_, _, end)| end)
+            .collect();
+        assert_eq!(ends, ["08:30:00", "09:10:00", "10:00:00"]);
+    }
+
+    #[test]
+    fn bells_outside_record_are_dropped() {
+        let ids: Vec<_> = bells(&expand(&timetable(), (8, 44), (9, 40)).unwrap())
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .collect();
+        assert_eq!(
+            ids,
+            ["t-lesson-1-end", "t-lesson-2-start", "t-lesson-2-end"]
+        );
+    }
+
+    #[test]
+    fn missing_break_or_bell_source_is_an_error() {
+        let mut timetable = timetable();
+        timetable.break_minutes = vec![10];
+        let error = expand(&timetable, (7, 0), (12, 0)).unwrap_err();
+        assert!(error.to_string().contains("перемена после урока 2"));
+
+        let mut timetable = self::timetable();
+        timetable.lesson_end_type_id = Some("missing".into());
+        let error = expand(&timetable, (7, 0), (12, 0)).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("не найден источник звонка missing"));
+    }
+
+    #[test]
+    fn unknown_variant_is_an_error() {
+        let mut timetable = timetable();
+        timetable.records[0].variant = Some("holiday".into());
+        assert!(expand(&timetable, (7, 0), (12, 0)).is_err());
+    }
+}
@@ -1,10 +1,10 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayingTime {
-    pub start: DateTime<Local>,
-    pub end: DateTime<Local>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +23,34 @@ pub struct Source {
     pub cut: Cut,
 }
 
+/// К какому краю слота привязано объявление, если длина источника
+/// не совпадает с длиной слота
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FixedTime {
+    Start,
+    End,
+    Center,
+}
+
+/// Край объявления, от которого отсчитывается смещение зависимого объявления
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnchorEdge {
+    Start,
+    End,
+}
+
+/// Положение объявления относительно другого объявления той же записи
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelativeTime {
+    #[serde(rename = "arrangementId")]
+    pub arrangement_id: String,
+    pub edge: AnchorEdge,
+    #[serde(rename = "offsetMs", default)]
+    pub offset_ms: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Arrangement {
     pub id: String,
@@ -35,18 +63,365 @@ pub struct Arrangement {
     pub fade_in: bool,
     #[serde(rename = "fadeOut")]
     pub fade_out: bool,
+    #[serde(rename = "fixedTime", default)]
+    pub fixed_time: Option<FixedTime>,
+    #[serde(rename = "relativeTo", default)]
+    pub relative_to: Option<RelativeTime>,
+}
+
+/// Шаблон времени срабатывания правила повторения
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RecurrencePattern {
+    /// Каждые N минут с `from` по `to` включительно
+    Every {
+        #[serde(rename = "intervalMinutes")]
+        interval_minutes: u32,
+        from: NaiveTime,
+        to: NaiveTime,
+    },
+    /// В перечисленное время
+    At { times: Vec<NaiveTime> },
+}
+
+/// Правило повторения, которое бэкенд разворачивает в объявления
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurrenceRule {
+    pub id: String,
+    #[serde(rename = "typeId")]
+    pub type_id: Option<String>,
+    pub pattern: RecurrencePattern,
+    /// Записи, к которым применяется правило (пусто — ко всем)
+    #[serde(default)]
+    pub records: Vec<String>,
+    /// Дни недели, в которые правило действует (пусто — каждый день)
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    #[serde(rename = "exceptTimes", default)]
+    pub except_times: Vec<NaiveTime>,
+    #[serde(rename = "exceptDates", default)]
+    pub except_dates: Vec<NaiveDate>,
+    /// Длительность слота; по умолчанию — длина обрезанного источника
+    #[serde(rename = "durationMs", default)]
+    pub duration_ms: Option<i64>,
+    pub loudness: Option<f32>,
+    #[serde(rename = "fadeIn", default)]
+    pub fade_in: bool,
+    #[serde(rename = "fadeOut", default)]
+    pub fade_out: bool,
+    #[serde(rename = "fixedTime", default)]
+    pub fixed_time: Option<FixedTime>,
+}
+
+/// Вариант расписания уроков, например сокращённый день
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimetableVariant {
+    pub name: String,
+    /// Максимальная длительность урока в минутах (более длинные уроки сокращаются)
+    #[serde(rename = "lessonMinutes")]
+    pub lesson_minutes: u32,
+    /// Перемены этого варианта; по умолчанию — как в основном расписании
+    #[serde(rename = "breakMinutes", default)]
+    pub break_minutes: Option<Vec<u32>>,
+}
+
+/// Запись, для которой генерируются звонки по расписанию уроков
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimetableRecord {
+    pub record: String,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+/// Расписание уроков, которое бэкенд разворачивает в звонки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timetable {
+    pub id: String,
+    #[serde(rename = "firstLessonStart")]
+    pub first_lesson_start: NaiveTime,
+    /// Длительность каждого урока в минутах
+    #[serde(rename = "lessonMinutes")]
+    pub lesson_minutes: Vec<u32>,
+    /// Перемена после каждого урока, кроме последнего, в минутах
+    #[serde(rename = "breakMinutes", default)]
+    pub break_minutes: Vec<u32>,
+    /// Источник звонка на урок
+    #[serde(rename = "lessonStartTypeId", default)]
+    pub lesson_start_type_id: Option<String>,
+    /// Источник звонка с урока
+    #[serde(rename = "lessonEndTypeId", default)]
+    pub lesson_end_type_id: Option<String>,
+    pub loudness: Option<f32>,
+    #[serde(rename = "fadeIn", default)]
+    pub fade_in: bool,
+    #[serde(rename = "fadeOut", default)]
+    pub fade_out: bool,
+    #[serde(default)]
+    pub variants: Vec<TimetableVariant>,
+    pub records: Vec<TimetableRecord>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimeOfRecord {
-    pub start: DateTime<Local>,
-    pub end: DateTime<Local>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Окно записи для предпрослушивания
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PreviewWindow {
+    /// Явный интервал времени
+    Range {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    /// Слот объявления плюс `paddingMs` до и после
+    Around {
+        #[serde(rename = "arrangementId")]
+        arrangement_id: String,
+        #[serde(rename = "paddingMs")]
+        padding_ms: i64,
+    },
+}
+
+/// Кодировщик для экспорта
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderBackend {
+    /// FFmpeg, если он найден и умеет формат, иначе встроенный кодировщик
+    #[default]
+    Auto,
+    Ffmpeg,
+    /// Встроенный кодировщик без внешних программ (WAV, FLAC)
+    Native,
+}
+
+/// Режим управления битрейтом Opus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpusVbr {
+    #[default]
+    On,
+    Off,
+    Constrained,
+}
+
+/// Профиль оптимизации кодека Opus (`-application`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpusApplication {
+    /// Музыка и смешанный контент
+    #[default]
+    Audio,
+    /// Речь
+    Voip,
+    /// Минимальная задержка
+    Lowdelay,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpusSettings {
+    #[serde(default)]
+    pub vbr: OpusVbr,
+    #[serde(default)]
+    pub application: OpusApplication,
+}
+
+/// Профиль AAC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AacProfile {
+    /// AAC-LC — совместим со всеми плеерами
+    #[default]
+    Lc,
+    /// HE-AAC для низких битрейтов (только `libfdk_aac`)
+    He,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AacSettings {
+    #[serde(default)]
+    pub profile: AacProfile,
+}
+
+/// Режим битрейта для MP3, Vorbis и AAC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BitrateMode {
+    /// Постоянный битрейт `bitrate`
+    #[default]
+    Cbr,
+    /// Переменный битрейт по уровню `quality`
+    Vbr,
+    /// Переменный битрейт со средним значением `bitrate`
+    Abr,
+}
+
+/// Разрядность несжатых и lossless форматов
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitDepth {
+    #[default]
+    #[serde(rename = "16")]
+    Int16,
+    #[serde(rename = "24")]
+    Int24,
+    #[serde(rename = "32f")]
+    Float32,
+}
+
+impl BitDepth {
+    /// Число бит целочисленного формата; `None` для float
+    pub fn integer_bits(self) -> Option<u32> {
+        match self {
+            Self::Int16 => Some(16),
+            Self::Int24 => Some(24),
+            Self::Float32 => None,
+        }
+    }
+}
+
+/// Дизеринг при понижении разрядности с float до целых
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    /// Простое округление
+    None,
+    /// Треугольный шум ±1 младший разряд
+    #[default]
+    Tpdf,
+    /// TPDF с формированием спектра шума
+    NoiseShaped,
+}
+
+/// Шаблоны тегов экспортируемых файлов.
+///
+/// Подстановки: `{record}` — имя записи, `{project}` — имя проекта,
+/// `{date}`, `{year}`, `{start}`, `{end}` — дата и время записи в часовом
+/// поясе проекта. Пустые после подстановки теги не пишутся.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagSettings {
+    pub enabled: bool,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub comment: Option<String>,
+    /// Произвольные поля (в WAV не записываются: в LIST/INFO их нет)
+    pub custom: std::collections::BTreeMap<String, String>,
+}
+
+impl Default for TagSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            title: Some("{record}".to_string()),
+            artist: None,
+            album: Some("{project}".to_string()),
+            date: Some("{date}".to_string()),
+            comment: None,
+            custom: Default::default(),
+        }
+    }
+}
+
+/// Главы по объявлениям записи
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChapterSettings {
+    /// Главы внутри файла (ID3 CHAP/CTOC, Vorbis CHAPTERxx, главы MP4)
+    pub enabled: bool,
+    /// Файл `.cue` рядом с экспортом
+    pub cue: bool,
+}
+
+impl Default for ChapterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cue: false,
+        }
+    }
+}
+
+/// Что делать, если файл результата уже существует
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    /// Заменить старый файл, когда новый полностью записан
+    #[default]
+    Overwrite,
+    /// Сохранить оба: к новому имени добавляется ` (1)`, ` (2)`, ...
+    KeepBoth,
+    /// Прервать экспорт записи с ошибкой
+    Fail,
+}
+
+/// Разбиение записи на несколько файлов
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum SplitMode {
+    /// Один файл на запись
+    #[default]
+    None,
+    /// Отрезки по `minutes` минут
+    Time { minutes: u32 },
+    /// Файл на каждое объявление
+    Arrangement,
+    /// Части не больше `megabytes` МБ (по оценке битрейта сверху)
+    Size { megabytes: u32 },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportSettings {
-    pub extension: String, // "mp3", "wav", "ogg", "flac"
+    pub extension: String, // "mp3", "wav", "ogg", "flac", "opus", "m4a" (AAC), "alac"
     pub bitrate: u32,      // 128, 192, 320, etc.
+    #[serde(default)]
+    pub encoder: EncoderBackend,
+    /// Режим битрейта MP3, Vorbis и AAC (у Opus свой — `opus.vbr`)
+    #[serde(default)]
+    pub mode: BitrateMode,
+    /// Качество VBR от 0 (наименьший файл) до 10 (наилучшее)
+    #[serde(default)]
+    pub quality: Option<u8>,
+    /// Разрядность WAV, FLAC и ALAC
+    #[serde(default)]
+    pub bit_depth: BitDepth,
+    #[serde(default)]
+    pub dither: Dither,
+    #[serde(default)]
+    pub opus: OpusSettings,
+    #[serde(default)]
+    pub aac: AacSettings,
+    #[serde(default)]
+    pub tags: TagSettings,
+    #[serde(default)]
+    pub chapters: ChapterSettings,
+    #[serde(default)]
+    pub split: SplitMode,
+    /// Декодировать записанный файл и сверить его с отрендеренной записью
+    #[serde(default)]
+    pub verify: bool,
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
+}
+
+impl ExportSettings {
+    /// Качество VBR по умолчанию (≈ V3 у MP3, q7 у Vorbis)
+    pub const DEFAULT_QUALITY: u8 = 7;
+
+    pub fn quality(&self) -> u8 {
+        self.quality.unwrap_or(Self::DEFAULT_QUALITY).min(10)
+    }
+
+    /// Расширение выходного файла: ALAC, как и AAC, пишется в контейнер M4A
+    pub fn file_extension(&self) -> &str {
+        match self.extension.as_str() {
+            "alac" => "m4a",
+            other => other,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,14 +429,187 @@ pub struct ExportRequest {
     pub sources: Vec<Source>,
     pub arrangements: std::collections::HashMap<String, Vec<Arrangement>>,
     pub time_of_records: std::collections::HashMap<String, TimeOfRecord>,
+    #[serde(default)]
+    pub recurrences: Vec<RecurrenceRule>,
+    #[serde(default)]
+    pub timetables: Vec<Timetable>,
+    /// Часовой пояс проекта (имя IANA, например "Europe/Moscow")
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    /// Имя проекта для тегов (`{project}`)
+    #[serde(default)]
+    pub project_name: Option<String>,
     pub settings: ExportSettings,
+    #[serde(default)]
     pub record_name: String, // Какую запись экспортировать
+    /// Записи для пакетного экспорта (пусто — все записи)
+    #[serde(default)]
+    pub record_names: Vec<String>,
+}
+
+/// Формат списка размещений записи для монтажёров
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineFormat {
+    /// Дорожка меток Audacity (`начало<TAB>конец<TAB>метка`)
+    Audacity,
+    Csv,
+    /// EDL в стиле CMX3600, 25 кадров/с
+    Edl,
+}
+
+/// Формат плейлиста для плееров, которые сами воспроизводят фрагменты
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistFormat {
+    /// Расширенный M3U в UTF-8; смещения в файле — опциями VLC
+    M3u8,
+    /// PLS: только пути, названия и длительности
+    Pls,
+    /// XSPF (XML Shareable Playlist Format)
+    Xspf,
+}
+
+/// Этап экспорта в событиях прогресса
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStage {
+    Loading,
+    Processing,
+    Encoding,
+    Completed,
+    Cancelled,
+    Error,
+}
+
+impl ExportStage {
+    /// Завершающий этап: после него событий для записи больше не будет
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Completed | Self::Cancelled | Self::Error)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportProgress {
-    pub stage: String, // "loading", "processing", "encoding", "completed", "error"
+    pub stage: ExportStage,
     pub progress: f32, // 0.0 - 100.0
     pub message: String,
     pub record_name: Option<String>,
+    /// Сколько прошло с начала экспорта записи
+    #[serde(default)]
+    pub elapsed_ms: Option<u64>,
+    /// Оценка оставшегося времени по текущему прогрессу
+    #[serde(default)]
+    pub eta_ms: Option<u64>,
+    /// Идентификатор задания экспорта (для отмены)
+    #[serde(default)]
+    pub job_id: Option<String>,
+}
+
+/// Индекс частей разбитой записи (`{record}_index.json`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitIndex {
+    pub record_name: String,
+    pub split: SplitMode,
+    pub parts: Vec<SplitPart>,
+}
+
+/// Часть разбитой записи
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitPart {
+    /// Имя файла части рядом с индексом
+    pub file: String,
+    /// Границы части от начала записи
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// Местное время начала и конца части
+    pub start_time: String,
+    pub end_time: String,
+    /// Объявление части в режиме `arrangement`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrangement_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Манифест компактного экспорта (`{record}_events.json`): каждый
+/// различный фрагмент записан один раз, события ссылаются на него по индексу
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventList {
+    pub record_name: String,
+    /// Местное время начала и конца записи
+    pub start_time: String,
+    pub end_time: String,
+    pub duration_ms: u64,
+    pub clips: Vec<EventClip>,
+    /// События по возрастанию начала
+    pub events: Vec<ScheduledEvent>,
+}
+
+/// Фрагмент компактного экспорта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventClip {
+    /// Путь к файлу фрагмента относительно манифеста
+    pub file: String,
+    pub title: String,
+    pub source_id: String,
+    pub duration_ms: u64,
+}
+
+/// Момент, когда в записи звучит фрагмент
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    /// Индекс в `EventList::clips`
+    pub clip: usize,
+    pub arrangement_id: String,
+    /// Начало от начала записи
+    pub offset_ms: u64,
+    /// Местное время начала
+    pub start_time: String,
+    pub duration_ms: u64,
+}
+
+/// Результат проверки записанного файла
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    /// Имя проверенного файла
+    pub file: String,
+    /// Длительность декодированного файла; `None`, если он не декодировался
+    pub duration_ms: Option<u64>,
+    pub expected_ms: u64,
+    /// SHA-256 файла в hex
+    pub sha256: String,
+    /// Тишина или клиппинг на местах объявлений и пропущенные проверки
+    pub warnings: Vec<String>,
+}
+
+/// Результат экспорта одной записи в пакетном экспорте
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordExportResult {
+    pub record_name: String,
+    pub output_path: Option<String>,
+    pub error: Option<String>,
+    /// Проверка каждого записанного файла (при `verify` в настройках)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verification: Vec<Verification>,
+}
+
+/// Доступность формата экспорта в найденном FFmpeg
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormatSupport {
+    pub extension: String,
+    /// Энкодер FFmpeg, которым кодируется формат
+    pub encoder: String,
+    pub available: bool,
+}
+
+/// Найденный FFmpeg и его возможности
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FfmpegInfo {
+    pub path: String,
+    /// Версия из `ffmpeg -version`, например "6.1.1"
+    pub version: String,
+    /// Аудиоэнкодеры из `ffmpeg -encoders`
+    pub encoders: Vec<String>,
+    pub formats: Vec<FormatSupport>,
 }
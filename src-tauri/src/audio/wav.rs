@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Предельный размер файла RIFF WAV: размеры чанков хранятся в 32 битах
+pub const RIFF_LIMIT: u64 = u32::MAX as u64;
+/// Заголовок обычного WAV: RIFF, fmt и заголовок data
+const WAV_HEADER_SIZE: u64 = 44;
+
+/// Не поместится ли моно запись из `sample_count` сэмплов в обычный WAV
+/// вместе с чанками размером `trailer_size`, дописанными после данных (`info_size`)
+pub fn needs_rf64(sample_count: usize, bytes_per_sample: u32, trailer_size: u64) -> bool {
+    let data_size = sample_count as u64 * bytes_per_sample as u64;
+    // Данные нечётной длины дополняются байтом выравнивания
+    WAV_HEADER_SIZE + data_size + data_size % 2 + trailer_size > RIFF_LIMIT
+}
+
+/// Размер чанка LIST/INFO, который `append_info` допишет для этих тегов
+pub fn info_size(entries: &[([u8; 4], &str)]) -> u64 {
+    if entries.is_empty() {
+        return 0;
+    }
+    8 + info_chunk(entries).len() as u64
+}
+
+/// Содержимое чанка LIST: тип INFO и подчанки тегов
+fn info_chunk(entries: &[([u8; 4], &str)]) -> Vec<u8> {
+    let mut info = b"INFO".to_vec();
+    for (id, value) in entries {
+        // Строки INFO завершаются нулём и выравниваются до чётной длины
+        let size = value.len() + 1;
+        info.extend_from_slice(id);
+        info.extend_from_slice(&(size as u32).to_le_bytes());
+        info.extend_from_slice(value.as_bytes());
+        info.push(0);
+        if size % 2 == 1 {
+            info.push(0);
+        }
+    }
+    info
+}
+
+/// Потоковая запись моно WAV в формате RF64 (EBU Tech 3306) для файлов
+/// больше 4 ГБ: 64-битные размеры хранятся в чанке ds64, а 32-битные поля
+/// RIFF заполняются 0xFFFFFFFF.
+pub struct Rf64Writer {
+    out: BufWriter<File>,
+    bytes_per_sample: u32,
+    float: bool,
+    data_size: u64,
+}
+
+impl Rf64Writer {
+    /// `bits` — 16 или 24 для целых сэмплов, 32 для float
+    pub fn create(path: &Path, sample_rate: u32, bits: u32, float: bool) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+        let mut writer = Self {
+            out: BufWriter::new(file),
+            bytes_per_sample: bits / 8,
+            float,
+            data_size: 0,
+        };
+        writer.write_header(sample_rate)?;
+        Ok(writer)
+    }
+
+    pub fn write_int(&mut self, sample: i32) -> Result<()> {
+        let bytes = sample.to_le_bytes();
+        self.out
+            .write_all(&bytes[..self.bytes_per_sample as usize])?;
+        self.data_size += self.bytes_per_sample as u64;
+        Ok(())
+    }
+
+    pub fn write_float(&mut self, sample: f32) -> Result<()> {
+        self.out.write_all(&sample.to_le_bytes())?;
+        self.data_size += 4;
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<()> {
+        // Данные нечётной длины выравниваются до чётной
+        if self.data_size % 2 == 1 {
+            self.out.write_all(&[0])?;
+        }
+        let file_size = self.out.stream_position()?;
+
+        // Размеры RIFF, data и число сэмплов в ds64
+        self.out.seek(SeekFrom::Start(20))?;
+        self.out.write_all(&(file_size - 8).to_le_bytes())?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out
+            .write_all(&(self.data_size / self.bytes_per_sample as u64).to_le_bytes())?;
+        self.out.flush().context("Failed to finalize RF64 file")?;
+        Ok(())
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> Result<()> {
+        let block_align = self.bytes_per_sample as u16;
+        let out = &mut self.out;
+
+        out.write_all(b"RF64")?;
+        out.write_all(&u32::MAX.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        // ds64: размеры RIFF и data, число сэмплов, пустая таблица
+        out.write_all(b"ds64")?;
+        out.write_all(&28u32.to_le_bytes())?;
+        out.write_all(&[0; 24])?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        let format_tag: u16 = if self.float { 3 } else { 1 };
+        out.write_all(&format_tag.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // моно
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&(block_align * 8).to_le_bytes())?;
+
+        out.write_all(b"data")?;
+        out.write_all(&u32::MAX.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Дописывает в конец готового WAV (RIFF или RF64) чанк LIST/INFO с тегами
+/// и исправляет размер RIFF
+pub fn append_info(path: &Path, entries: &[([u8; 4], &str)]) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let info = info_chunk(entries);
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut form = [0u8; 4];
+    std::io::Read::read_exact(&mut file, &mut form)?;
+
+    let mut end = file.seek(SeekFrom::End(0))?;
+    // Данные нечётной длины дополняются байтом, не входящим в размер data
+    if end % 2 == 1 {
+        file.write_all(&[0])?;
+        end += 1;
+    }
+    file.write_all(b"LIST")?;
+    file.write_all(&(info.len() as u32).to_le_bytes())?;
+    file.write_all(&info)?;
+    // Файл после LIST без 8 байт заголовка RIFF
+    let riff_size = end + info.len() as u64;
+
+    match &form {
+        b"RIFF" => {
+            let riff_size = u32::try_from(riff_size).context("WAV с тегами больше 4 ГБ")?;
+            file.seek(SeekFrom::Start(4))?;
+            file.write_all(&riff_size.to_le_bytes())?;
+        }
+        // Размер RF64 хранится в ds64
+        b"RF64" => {
+            file.seek(SeekFrom::Start(20))?;
+            file.write_all(&riff_size.to_le_bytes())?;
+        }
+        _ => anyhow::bail!("{} не является WAV", path.display()),
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn rf64_header_and_info() {
+        let path = std::env::temp_dir().join("gensoundad_rf64_test.wav");
+        let mut writer = Rf64Writer::create(&path, 44100, 24, false).unwrap();
+        for sample in [1, -1, 0x12_3456] {
+            writer.write_int(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        let entries = [(*b"INAM", "Запись")];
+        append_info(&path, &entries).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(&bytes[0..4], b"RF64");
+        assert_eq!(u32_at(&bytes, 4), u32::MAX);
+        assert_eq!(&bytes[8..16], b"WAVEds64");
+        assert_eq!(u32_at(&bytes, 16), 28);
+        assert_eq!(u64_at(&bytes, 20), bytes.len() as u64 - 8);
+        assert_eq!(u64_at(&bytes, 28), 9);
+        assert_eq!(u64_at(&bytes, 36), 3);
+
+        // fmt: PCM, моно, 44100 Гц, 3 байта на сэмпл
+        assert_eq!(&bytes[48..52], b"fmt ");
+        assert_eq!(u32_at(&bytes, 52), 16);
+        assert_eq!(&bytes[56..60], [1, 0, 1, 0]);
+        assert_eq!(u32_at(&bytes, 60), 44100);
+        assert_eq!(u32_at(&bytes, 64), 44100 * 3);
+        assert_eq!(&bytes[68..72], [3, 0, 24, 0]);
+
+        assert_eq!(&bytes[72..76], b"data");
+        assert_eq!(u32_at(&bytes, 76), u32::MAX);
+        assert_eq!(
+            &bytes[80..89],
+            [1, 0, 0, 0xFF, 0xFF, 0xFF, 0x56, 0x34, 0x12]
+        );
+        // Байт выравнивания нечётных данных, затем LIST/INFO
+        assert_eq!(bytes[89], 0);
+        assert_eq!(&bytes[90..94], b"LIST");
+        assert_eq!(bytes.len() as u64, 90 + info_size(&entries));
+    }
+
+    #[test]
+    fn tags_count_towards_riff_limit() {
+        let entries = [(*b"ICMT", "комментарий")];
+        // Ровно на пределе без тегов
+        let samples = ((RIFF_LIMIT - WAV_HEADER_SIZE) / 2) as usize;
+        assert!(!needs_rf64(samples, 2, 0));
+        assert!(needs_rf64(samples, 2, info_size(&entries)));
+        assert_eq!(info_size(&[]), 0);
+        // "комментарий" — 22 байта и ноль, выравнивание до 24
+        assert_eq!(info_size(&entries), 8 + 4 + 8 + 24);
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use chrono::{
+    DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Часовой пояс проекта, через который настенное время (правила повторения,
+/// расписания уроков, дни недели) переводится в моменты времени.
+///
+/// Запись раскладывается по реально прошедшему времени: запись 00:00–06:00,
+/// пересекающая перевод часов назад, длится 7 часов, вперёд — 5 часов.
+/// Неоднозначное настенное время (перевод назад) берётся по первому наступлению,
+/// несуществующее (перевод вперёд) сдвигается вперёд на величину перевода.
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectZone {
+    Named(Tz),
+    /// Пояс компьютера — для проектов, сохранённых без `time_zone`
+    Local,
+}
+
+impl ProjectZone {
+    /// Пояс по имени IANA; без имени (или с пустым) — пояс компьютера
+    pub fn from_name(name: Option<&str>) -> Result<Self> {
+        match name.filter(|name| !name.trim().is_empty()) {
+            Some(name) => name
+                .parse::<Tz>()
+                .map(Self::Named)
+                .map_err(|e| anyhow::anyhow!("Неизвестный часовой пояс {name}: {e}")),
+            None => Ok(Self::Local),
+        }
+    }
+
+    /// Календарная дата момента времени в поясе проекта
+    pub fn local_date(&self, time: &DateTime<Utc>) -> NaiveDate {
+        self.local_datetime(time).date()
+    }
+
+    /// Настенное время момента в поясе проекта
+    pub fn local_datetime(&self, time: &DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Named(tz) => time.with_timezone(tz).naive_local(),
+            Self::Local => time.with_timezone(&Local).naive_local(),
+        }
+    }
+
+    /// Переводит настенное время в поясе проекта в момент времени
+    pub fn resolve(&self, time: NaiveDateTime) -> Result<DateTime<Utc>> {
+        match self {
+            Self::Named(tz) => resolve_in(tz, time),
+            Self::Local => resolve_in(&Local, time),
+        }
+        .with_context(|| format!("Не удалось перевести время {time} в часовой пояс проекта"))
+    }
+
+    /// Меняется ли смещение от UTC между двумя моментами (перевод часов)
+    pub fn crosses_transition(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+        let offset = |time: &DateTime<Utc>| match self {
+            Self::Named(tz) => time.with_timezone(tz).offset().fix(),
+            Self::Local => time.with_timezone(&Local).offset().fix(),
+        };
+        offset(start) != offset(end)
+    }
+}
+
+fn resolve_in<Z: TimeZone>(zone: &Z, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match zone.from_local_datetime(&time) {
+        LocalResult::Single(t) => Some(t.with_timezone(&Utc)),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.with_timezone(&Utc)),
+        LocalResult::None => {
+            // Время попало в «дыру» перевода вперёд: берём смещение,
+            // действовавшее до перевода
+            let before = zone
+                .from_local_datetime(&(time - Duration::days(1)))
+                .earliest()?;
+            before
+                .offset()
+                .fix()
+                .from_local_datetime(&time)
+                .single()
+                .map(|t| t.with_timezone(&Utc))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::timeline::Timeline;
+    use crate::audio::types::TimeOfRecord;
+
+    fn berlin() -> ProjectZone {
+        ProjectZone::from_name(Some("Europe/Berlin")).unwrap()
+    }
+
+    fn wall(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn utc(date: &str, time: &str) -> DateTime<Utc> {
+        wall(date, time).and_utc()
+    }
+
+    #[test]
+    fn spring_forward_gap_uses_offset_before_transition() {
+        // 31.03.2024 часы в Берлине переводятся с 02:00 CET на 03:00 CEST
+        let zone = berlin();
+        assert_eq!(
+            zone.resolve(wall("2024-03-31", "02:30")).unwrap(),
+            utc("2024-03-31", "01:30")
+        );
+        assert_eq!(
+            zone.resolve(wall("2024-03-31", "03:00")).unwrap(),
+            utc("2024-03-31", "01:00")
+        );
+    }
+
+    #[test]
+    fn fall_back_repeated_hour_uses_first_occurrence() {
+        // 27.10.2024 час 02:00–03:00 повторяется: сначала CEST, затем CET
+        let zone = berlin();
+        assert_eq!(
+            zone.resolve(wall("2024-10-27", "02:30")).unwrap(),
+            utc("2024-10-27", "00:30")
+        );
+        assert_eq!(
+            zone.local_datetime(&utc("2024-10-27", "01:30")),
+            wall("2024-10-27", "02:30")
+        );
+    }
+
+    #[test]
+    fn record_across_transition_lasts_elapsed_time() {
+        let zone = berlin();
+        for (date, hours) in [("2024-10-27", 7), ("2024-03-31", 5)] {
+            let record = TimeOfRecord {
+                start: zone.resolve(wall(date, "00:00")).unwrap(),
+                end: zone.resolve(wall(date, "06:00")).unwrap(),
+            };
+            assert!(zone.crosses_transition(&record.start, &record.end));
+            let timeline = Timeline::new(&record, 44100);
+            assert_eq!(timeline.len(), hours * 3600 * 44100);
+        }
+    }
+
+    #[test]
+    fn missing_name_falls_back_to_local() {
+        assert!(matches!(
+            ProjectZone::from_name(None).unwrap(),
+            ProjectZone::Local
+        ));
+        assert!(matches!(
+            ProjectZone::from_name(Some("")).unwrap(),
+            ProjectZone::Local
+        ));
+        // Опечатка в имени — ошибка, а не тихий сдвиг расписания
+        let error = ProjectZone::from_name(Some("Europe/Berlinn")).unwrap_err();
+        assert!(error.to_string().contains("Europe/Berlinn"));
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::export::metadata::Chapter;
+
+/// Кадров в секунде в адресации CUE (mm:ss:ff)
+const CUE_FRAMES: u64 = 75;
+
+/// `.cue` к файлу `audio_path` с треком на каждое объявление.
+///
+/// Текст в UTF-8 с BOM: по нему плееры распознают кириллицу в названиях.
+pub fn cue_sheet(audio_path: &Path, title: &str, chapters: &[Chapter]) -> Result<String> {
+    let file_name = audio_path
+        .file_name()
+        .context("Нет имени файла для cue")?
+        .to_string_lossy();
+    let file_type = match audio_path.extension().and_then(|e| e.to_str()) {
+        Some("mp3") => "MP3",
+        _ => "WAVE",
+    };
+
+    let mut cue = String::from("\u{feff}");
+    writeln!(cue, "REM GENERATOR \"GenSoundAD\"")?;
+    writeln!(cue, "TITLE \"{}\"", quote(title))?;
+    writeln!(cue, "FILE \"{}\" {}", quote(&file_name), file_type)?;
+
+    // Индексы треков обязаны возрастать: совпадающие по кадру объявления
+    // (наложения) объединяются в один трек
+    let mut last_frame = None;
+    let mut track = 0;
+    for chapter in chapters {
+        let frame = chapter.start_ms * CUE_FRAMES / 1000;
+        if last_frame.is_some_and(|last| frame <= last) {
+            continue;
+        }
+        track += 1;
+        writeln!(cue, "  TRACK {track:02} AUDIO")?;
+        writeln!(cue, "    TITLE \"{}\"", quote(&chapter.title))?;
+        if track == 1 && frame > 0 {
+            writeln!(cue, "    INDEX 00 00:00:00")?;
+        }
+        writeln!(cue, "    INDEX 01 {}", timestamp(frame))?;
+        last_frame = Some(frame);
+    }
+
+    Ok(cue)
+}
+
+/// mm:ss:ff; минуты не ограничены 99 — записи длятся часами
+fn timestamp(frame: u64) -> String {
+    let seconds = frame / CUE_FRAMES;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 60,
+        seconds % 60,
+        frame % CUE_FRAMES
+    )
+}
+
+/// В CUE нет экранирования, двойные кавычки заменяются одинарными
+fn quote(value: &str) -> String {
+    value.replace('"', "'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(title: &str, start_ms: u64) -> Chapter {
+        Chapter {
+            title: title.into(),
+            start_ms,
+            end_ms: start_ms + 1000,
+        }
+    }
+
+    #[test]
+    fn cue_sheet_golden() {
+        let chapters = [
+            chapter("Звонок \"на урок\"", 1013),
+            chapter("Гимн", 1020),
+            // Тот же кадр, что у предыдущего, — трек не создаётся
+            chapter("Наложение", 1025),
+            chapter("Вечер", 3_725_500),
+        ];
+        let cue = cue_sheet(Path::new("/out/monday.mp3"), "Понедельник", &chapters).unwrap();
+        assert_eq!(
+            cue,
+            "\u{feff}REM GENERATOR \"GenSoundAD\"\n\
+             TITLE \"Понедельник\"\n\
+             FILE \"monday.mp3\" MP3\n  \
+             TRACK 01 AUDIO\n    \
+             TITLE \"Звонок 'на урок'\"\n    \
+             INDEX 00 00:00:00\n    \
+             INDEX 01 00:01:00\n  \
+             TRACK 02 AUDIO\n    \
+             TITLE \"Гимн\"\n    \
+             INDEX 01 00:01:01\n  \
+             TRACK 03 AUDIO\n    \
+             TITLE \"Вечер\"\n    \
+             INDEX 01 62:05:37\n"
+        );
+    }
+
+    #[test]
+    fn first_track_at_zero_has_no_pregap() {
+        let cue = cue_sheet(Path::new("monday.wav"), "monday", &[chapter("a", 5)]).unwrap();
+        assert!(cue.contains("FILE \"monday.wav\" WAVE\n"));
+        assert!(cue.ends_with("  TRACK 01 AUDIO\n    TITLE \"a\"\n    INDEX 01 00:00:00\n"));
+    }
+}
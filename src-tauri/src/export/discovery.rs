@@ -0,0 +1,317 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::audio::*;
+
+/// Минимальная поддерживаемая версия FFmpeg (major, minor)
+pub const MIN_VERSION: (u32, u32) = (4, 0);
+
+/// Энкодеры FFmpeg для каждого формата `ExportSettings` в порядке предпочтения.
+/// Для WAV указан 16-битный PCM; энкодер другой разрядности проверяет
+/// `ensure_format`
+pub const FORMAT_ENCODERS: &[(&str, &[&str])] = &[
+    ("mp3", &["libmp3lame"]),
+    ("ogg", &["libvorbis"]),
+    ("flac", &["flac"]),
+    ("wav", &["pcm_s16le"]),
+    ("opus", &["libopus"]),
+    ("m4a", &["libfdk_aac", "aac"]),
+    ("alac", &["alac"]),
+];
+
+/// Допустимый битрейт Opus для моно, кбит/с
+const OPUS_BITRATE: std::ops::RangeInclusive<u32> = 6..=256;
+
+#[cfg(target_os = "windows")]
+const FFMPEG_NAME: &str = "ffmpeg.exe";
+#[cfg(not(target_os = "windows"))]
+const FFMPEG_NAME: &str = "ffmpeg";
+
+/// Кандидаты в порядке приоритета: путь из настроек пользователя,
+/// встроенный в bundle FFmpeg, затем FFmpeg из `PATH`
+pub fn candidates(configured: Option<&Path>, bundled: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = configured.map(Path::to_path_buf).into_iter().collect();
+    candidates.extend(bundled);
+    if let Some(paths) = std::env::var_os("PATH") {
+        candidates.extend(
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(FFMPEG_NAME))
+                .filter(|path| path.is_file()),
+        );
+    }
+    candidates
+}
+
+/// Находит первый рабочий FFmpeg среди кандидатов и определяет его возможности
+pub fn locate(candidates: &[PathBuf]) -> Result<FfmpegInfo> {
+    let mut errors = Vec::new();
+    for path in candidates {
+        match probe(path) {
+            Ok(info) => {
+                log::info!("Используется FFmpeg {} ({})", info.version, info.path);
+                return Ok(info);
+            }
+            Err(e) => {
+                log::warn!("FFmpeg {} не подходит: {e:#}", path.display());
+                errors.push(format!("{}: {e:#}", path.display()));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        bail!("FFmpeg не найден ни в настройках, ни в bundle, ни в PATH");
+    }
+    bail!("Не найден подходящий FFmpeg:\n{}", errors.join("\n"))
+}
+
+/// Проверяет версию FFmpeg и собирает список аудиоэнкодеров
+pub fn probe(path: &Path) -> Result<FfmpegInfo> {
+    let version_output = run(path, &["-version"]).context("Не удалось запустить")?;
+    let version = version_of(&version_output)
+        .context("Неизвестный вывод -version, это не FFmpeg")?
+        .to_string();
+
+    match parse_version(&version) {
+        Some(parsed) if parsed < MIN_VERSION => bail!(
+            "версия {version} устарела, требуется {}.{} или новее",
+            MIN_VERSION.0,
+            MIN_VERSION.1
+        ),
+        Some(_) => {}
+        // Сборки из git ("N-113000-g...") версии не сообщают
+        None => log::warn!("Не удалось разобрать версию FFmpeg '{version}', считаем подходящей"),
+    }
+
+    let encoders = parse_audio_encoders(&run(path, &["-hide_banner", "-encoders"])?);
+    let formats = FORMAT_ENCODERS
+        .iter()
+        .map(|(extension, preferred)| {
+            let available = preferred
+                .iter()
+                .find(|encoder| encoders.iter().any(|e| e == *encoder));
+            FormatSupport {
+                extension: extension.to_string(),
+                encoder: available.unwrap_or(&preferred[0]).to_string(),
+                available: available.is_some(),
+            }
+        })
+        .collect();
+
+    Ok(FfmpegInfo {
+        path: path.to_string_lossy().to_string(),
+        version,
+        encoders,
+        formats,
+    })
+}
+
+/// Проверяет, что FFmpeg умеет кодировать формат с заданными настройками,
+/// и возвращает энкодер, которым он будет кодироваться
+pub fn ensure_format(info: &FfmpegInfo, settings: &ExportSettings) -> Result<String> {
+    let extension = settings.extension.as_str();
+    let format = match info.formats.iter().find(|f| f.extension == extension) {
+        Some(format) if format.available => format,
+        Some(format) => bail!(
+            "FFmpeg {} собран без энкодера {}, формат {} недоступен",
+            info.version,
+            format.encoder,
+            extension
+        ),
+        None => bail!("Неподдерживаемый формат: {}", extension),
+    };
+
+    match extension {
+        "opus" if !OPUS_BITRATE.contains(&settings.bitrate) => bail!(
+            "Битрейт Opus для моно должен быть от {} до {} кбит/с",
+            OPUS_BITRATE.start(),
+            OPUS_BITRATE.end()
+        ),
+        "m4a" if settings.aac.profile == AacProfile::He && format.encoder != "libfdk_aac" => {
+            bail!(
+                "HE-AAC требует FFmpeg с libfdk_aac, доступен только {}",
+                format.encoder
+            )
+        }
+        "wav" => {
+            let pcm = pcm_encoder(settings.bit_depth);
+            if !info.encoders.iter().any(|e| e == pcm) {
+                bail!(
+                    "FFmpeg {} собран без энкодера {}, WAV такой разрядности недоступен",
+                    info.version,
+                    pcm
+                );
+            }
+            return Ok(pcm.to_string());
+        }
+        _ => {}
+    }
+
+    Ok(format.encoder.clone())
+}
+
+fn run(path: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new(path).args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "{} завершился с кодом {}",
+            args.join(" "),
+            output.status.code().unwrap_or(-1)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// PCM-энкодер WAV для разрядности
+fn pcm_encoder(bit_depth: BitDepth) -> &'static str {
+    match bit_depth {
+        BitDepth::Int16 => "pcm_s16le",
+        BitDepth::Int24 => "pcm_s24le",
+        BitDepth::Float32 => "pcm_f32le",
+    }
+}
+
+/// Версия из первой строки `ffmpeg -version`
+/// ("ffmpeg version 6.1.1-3ubuntu5 Copyright ...")
+fn version_of(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .next()?
+        .strip_prefix("ffmpeg version ")?
+        .split_whitespace()
+        .next()
+}
+
+/// Разбирает "6.1.1-3ubuntu5", "n7.0", "4.4.2-static" в (major, minor)
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let version = version.strip_prefix('n').unwrap_or(version);
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(str::parse::<u32>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().and_then(|p| p.ok()).unwrap_or(0);
+    Some((major, minor))
+}
+
+/// Имена аудиоэнкодеров из таблицы `ffmpeg -encoders`
+/// (строки вида " A....D libmp3lame  libmp3lame MP3 ...")
+fn parse_audio_encoders(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.next()?;
+            let name = fields.next()?;
+            flags.starts_with('A').then(|| name.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODERS: &str = "\
+Encoders:
+ V..... = Video
+ A..... = Audio
+ S..... = Subtitle
+ .F.... = Frame-level multithreading
+ ..S... = Slice-level multithreading
+ ...X.. = Codec is experimental
+ ....B. = Supports draw_horiz_band
+ .....D = Supports direct rendering method 1
+ ------
+ V....D a64multi             Multicolor charset for Commodore 64 (codec c64_multicolor)
+ V..... libx264              libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10 (codec h264)
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D flac                 FLAC (Free Lossless Audio Codec)
+ A....D libmp3lame           libmp3lame MP3 (MPEG audio layer 3) (codec mp3)
+ A....D pcm_s16le            PCM signed 16-bit little-endian
+ A....D pcm_s24le            PCM signed 24-bit little-endian
+ S..... ass                  ASS (Advanced SubStation Alpha) subtitle
+";
+
+    fn info() -> FfmpegInfo {
+        let encoders = parse_audio_encoders(ENCODERS);
+        let formats = FORMAT_ENCODERS
+            .iter()
+            .map(|(extension, preferred)| FormatSupport {
+                extension: extension.to_string(),
+                encoder: preferred[0].to_string(),
+                available: encoders.iter().any(|e| e == preferred[0]),
+            })
+            .collect();
+        FfmpegInfo {
+            path: "ffmpeg".into(),
+            version: "6.1.1".into(),
+            encoders,
+            formats,
+        }
+    }
+
+    #[test]
+    fn version_from_real_output() {
+        let outputs = [
+            (
+                "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\n\
+                 built with gcc 13 (Ubuntu 13.2.0-23ubuntu3)",
+                "6.1.1-3ubuntu5",
+                Some((6, 1)),
+            ),
+            (
+                "ffmpeg version 4.4.2-0ubuntu0.22.04.1 Copyright (c) 2000-2021 the FFmpeg developers",
+                "4.4.2-0ubuntu0.22.04.1",
+                Some((4, 4)),
+            ),
+            (
+                "ffmpeg version 5.1.6-0+deb12u1 Copyright (c) 2000-2024 the FFmpeg developers",
+                "5.1.6-0+deb12u1",
+                Some((5, 1)),
+            ),
+            (
+                "ffmpeg version n7.0 Copyright (c) 2000-2024 the FFmpeg developers",
+                "n7.0",
+                Some((7, 0)),
+            ),
+            (
+                "ffmpeg version 7.1-full_build-www.gyan.dev Copyright (c) 2000-2024",
+                "7.1-full_build-www.gyan.dev",
+                Some((7, 1)),
+            ),
+            (
+                "ffmpeg version N-113000-g0a5813fc68-static https://johnvansickle.com/ffmpeg/",
+                "N-113000-g0a5813fc68-static",
+                None,
+            ),
+        ];
+        for (output, version, parsed) in outputs {
+            assert_eq!(version_of(output), Some(version));
+            assert_eq!(parse_version(version), parsed, "{version}");
+        }
+        assert_eq!(version_of("avconv version 12"), None);
+    }
+
+    #[test]
+    fn audio_encoders_from_real_output() {
+        assert_eq!(
+            parse_audio_encoders(ENCODERS),
+            ["aac", "flac", "libmp3lame", "pcm_s16le", "pcm_s24le"]
+        );
+    }
+
+    #[test]
+    fn wav_encoder_follows_bit_depth() {
+        let info = info();
+        let mut settings: ExportSettings =
+            serde_json::from_str(r#"{"extension":"wav","bitrate":192}"#).unwrap();
+        assert_eq!(ensure_format(&info, &settings).unwrap(), "pcm_s16le");
+        settings.bit_depth = BitDepth::Int24;
+        assert_eq!(ensure_format(&info, &settings).unwrap(), "pcm_s24le");
+        settings.bit_depth = BitDepth::Float32;
+        let error = ensure_format(&info, &settings).unwrap_err();
+        assert!(error.to_string().contains("pcm_f32le"));
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{Context, Result};
+use chrono::Duration;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use super::metadata::{clock, Metadata};
+use super::playlist::{self, Playlist};
+use super::{output, split, Encoder};
+use crate::audio::zone::ProjectZone;
+use crate::audio::*;
+
+/// Компактный экспорт записи: каждый различный фрагмент (после обрезки,
+/// фейдов и громкости) кодируется один раз в `{record}_clips/`, а когда
+/// он звучит, описывают `{record}_events.json` и `{record}_events.m3u`.
+/// Тишина между объявлениями не рендерится и не кодируется. Плейлисты
+/// `playlists` ссылаются на те же фрагменты.
+#[allow(clippy::too_many_arguments)]
+pub fn export_event_list(
+    processor: &AudioProcessor,
+    request: &ExportRequest,
+    record_name: &str,
+    audio_cache: &SourceCache,
+    output_dir: &Path,
+    encoder: &Encoder,
+    playlists: &[PlaylistFormat],
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<Vec<PathBuf>> {
+    let arrangements = schedule::resolve_record(request, record_name)?;
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Time record not found")?;
+    let zone = ProjectZone::from_name(request.time_zone.as_deref())?;
+    let wall_clock = |ms: u64| {
+        zone.local_datetime(&(time_record.start + Duration::milliseconds(ms as i64)))
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string()
+    };
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Processing,
+        progress: 30.0,
+        message: format!("Рендеринг фрагментов записи {record_name}"),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+    let clips = processor.render_clips(
+        &arrangements,
+        time_record,
+        &request.sources,
+        audio_cache,
+        cancel,
+    )?;
+
+    // Все имена выбираются до кодирования: `fail` не тратит время на
+    // фрагменты, которые некуда сохранить
+    let policy = request.settings.overwrite;
+    let clips_dir_name = format!("{record_name}_clips");
+    let clips_dir = output_dir.join(&clips_dir_name);
+    let clip_paths = clips
+        .iter()
+        .enumerate()
+        .map(|(i, clip)| {
+            let file_name = format!(
+                "{:03}_{}.{}",
+                i + 1,
+                split::sanitize(&clip.markers[0].title),
+                request.settings.file_extension()
+            );
+            output::resolve(&clips_dir.join(file_name), policy)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let json_path = output::resolve(
+        &output_dir.join(format!("{record_name}_events.json")),
+        policy,
+    )?;
+    let m3u_path = output::resolve(
+        &output_dir.join(format!("{record_name}_events.m3u")),
+        policy,
+    )?;
+    let playlist_paths = playlist::playlist_paths(record_name, playlists, output_dir, policy)?;
+
+    let created_dir = !clips_dir.exists();
+    std::fs::create_dir_all(&clips_dir)
+        .with_context(|| format!("Не удалось создать {}", clips_dir.display()))?;
+
+    let metadata = Metadata::resolve(request, record_name)?;
+    let duration_ms = (time_record.end - time_record.start)
+        .num_milliseconds()
+        .max(0) as u64;
+    let mut list = EventList {
+        record_name: record_name.to_string(),
+        start_time: wall_clock(0),
+        end_time: wall_clock(duration_ms),
+        duration_ms,
+        clips: Vec::with_capacity(clips.len()),
+        events: Vec::new(),
+    };
+
+    // Фрагменты и списки заменяют прежние только все вместе, после
+    // кодирования последнего фрагмента
+    let mut staged = output::Staged::default();
+    let result = (|| -> Result<Vec<PathBuf>> {
+        for (i, (clip, path)) in clips.iter().zip(&clip_paths).enumerate() {
+            cancel.check()?;
+            let ms = |position: usize| position as u64 * 1000 / clip.sample_rate as u64;
+            let first = &clip.markers[0];
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            progress_callback(ExportProgress {
+                stage: ExportStage::Encoding,
+                progress: 40.0 + i as f32 / clips.len() as f32 * 50.0,
+                message: format!("Кодирование фрагмента {}/{}", i + 1, clips.len()),
+                record_name: Some(record_name.to_string()),
+                elapsed_ms: None,
+                eta_ms: None,
+                job_id: None,
+            });
+
+            let mut clip_metadata = metadata.clone();
+            if let Some((_, title)) = clip_metadata
+                .tags
+                .iter_mut()
+                .find(|(key, _)| key == "title")
+            {
+                *title = first.title.clone();
+            }
+            // Прогресс кодирования отдельного фрагмента не показывается
+            let temp = staged.temp(path);
+            encoder.encode(
+                &clip.samples,
+                clip.sample_rate,
+                &temp,
+                &request.settings,
+                &clip_metadata,
+                record_name,
+                cancel,
+                |_| {},
+            )?;
+
+            list.clips.push(EventClip {
+                file: format!("{clips_dir_name}/{file_name}"),
+                title: first.title.clone(),
+                source_id: first.source_id.clone(),
+                duration_ms: ms(clip.samples.len()),
+            });
+            list.events
+                .extend(clip.markers.iter().map(|marker| ScheduledEvent {
+                    clip: i,
+                    arrangement_id: marker.arrangement_id.clone(),
+                    offset_ms: ms(marker.range.start),
+                    start_time: wall_clock(ms(marker.range.start)),
+                    duration_ms: ms(marker.range.len()),
+                }));
+        }
+        list.events.sort_by_key(|event| event.offset_ms);
+
+        staged.write(&json_path, serde_json::to_string_pretty(&list)?)?;
+        staged.write(&m3u_path, m3u(&list))?;
+        playlist::write_playlists(
+            &Playlist::from_event_list(&list),
+            &playlist_paths,
+            &mut staged,
+        )?;
+
+        let mut paths = vec![json_path, m3u_path];
+        paths.extend(playlist_paths.into_iter().map(|(_, path)| path));
+        Ok(paths)
+    })()
+    .and_then(|paths| staged.commit().map(|()| paths));
+
+    if result.is_err() && created_dir {
+        // Временные файлы уже удалены; папка — только созданная этим экспортом
+        let _ = std::fs::remove_dir(&clips_dir);
+    }
+    let paths = result?;
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Completed,
+        progress: 100.0,
+        message: format!(
+            "Экспорт завершен: {} фрагментов, {} событий",
+            list.clips.len(),
+            list.events.len()
+        ),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+    Ok(paths)
+}
+
+/// Расширенный M3U: запись на каждое событие, смещение от начала записи и
+/// местное время — в атрибутах `#EXTINF`
+fn m3u(list: &EventList) -> String {
+    let mut text = format!("#EXTM3U\n#PLAYLIST:{}\n", list.record_name);
+    for event in &list.events {
+        let clip = &list.clips[event.clip];
+        let _ = writeln!(
+            text,
+            "#EXTINF:{:.3} offset=\"{}\" time=\"{}\",{}\n{}",
+            event.duration_ms as f64 / 1000.0,
+            clock(event.offset_ms),
+            event.start_time,
+            clip.title,
+            clip.file
+        );
+    }
+    text
+}
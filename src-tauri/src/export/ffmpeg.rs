@@ -0,0 +1,542 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio::*;
+use crate::export::metadata::Metadata;
+
+/// Кодирует моно f32 сэмплы через FFmpeg в формат из настроек экспорта.
+///
+/// `codec` — энкодер FFmpeg, выбранный `discovery::ensure_format`.
+#[allow(clippy::too_many_arguments)]
+pub fn export_samples_with_ffmpeg(
+    samples: &[f32],
+    sample_rate: u32,
+    output_path: &Path,
+    settings: &ExportSettings,
+    metadata: &Metadata,
+    ffmpeg_path: &Path,
+    codec: &str,
+    record_name: &str,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<()> {
+    let expected_duration = samples.len() as f64 / sample_rate as f64;
+    let hours = (expected_duration / 3600.0) as u32;
+    let minutes = ((expected_duration % 3600.0) / 60.0) as u32;
+    let seconds = (expected_duration % 60.0) as u32;
+
+    log::info!(
+        "Экспорт {} сэмплов в {}, длительность: {}:{:02}:{:02}",
+        samples.len(),
+        settings.extension,
+        hours,
+        minutes,
+        seconds
+    );
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Encoding,
+        progress: 80.0,
+        message: format!("Кодирование в {}", settings.extension),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+
+    let metadata_args = metadata.ffmpeg_args();
+    let input_rate = sample_rate.to_string();
+    let mut args = vec![
+        "-f",
+        "f32le",
+        "-ar",
+        &input_rate,
+        "-ac",
+        "1",
+        "-i",
+        "pipe:0",
+    ];
+
+    // Главы передаются вторым входом в формате FFMETADATA1; WAV их не хранит
+    let chapters_file = if metadata.chapters.is_empty() || settings.extension == "wav" {
+        None
+    } else {
+        let path = std::env::temp_dir().join(format!("chapters-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, metadata.ffmetadata())?;
+        Some(TempFile(path))
+    };
+    let chapters_path = chapters_file
+        .as_ref()
+        .map(|file| file.0.to_string_lossy().to_string());
+    if let Some(path) = &chapters_path {
+        args.extend_from_slice(&[
+            "-f",
+            "ffmetadata",
+            "-i",
+            path,
+            "-map",
+            "0:a",
+            "-map_chapters",
+            "1",
+        ]);
+    }
+    let bitrate = format!("{}k", settings.bitrate);
+    let quality = settings.quality() as f32;
+    // Шкала качества VBR у каждого энкодера своя, настройка 0–10 переводится в неё
+    let vbr_quality = match codec {
+        // У LAME 0 — лучшее качество, 9 — худшее
+        "libmp3lame" => format!("{}", (9.0 - quality * 0.9).round()),
+        "libfdk_aac" => format!("{}", (1.0 + quality * 0.4).round()),
+        // Встроенный aac: -q:a от 0.1 до 2
+        "aac" => format!("{:.2}", 0.1 + quality * 0.19),
+        _ => format!("{quality}"),
+    };
+
+    // Произвольные теги MP4 FFmpeg пишет только с use_metadata_tags
+    let movflags = if metadata.has_custom() {
+        "+faststart+use_metadata_tags"
+    } else {
+        "+faststart"
+    };
+
+    match settings.extension.as_str() {
+        "mp3" => {
+            // ID3v2.3 читают и старые плееры, и Windows
+            args.extend_from_slice(&["-codec:a", "libmp3lame", "-id3v2_version", "3"]);
+            match settings.mode {
+                BitrateMode::Cbr => {
+                    args.extend_from_slice(&["-b:a", &bitrate, "-cbr", "1", "-reservoir", "0"])
+                }
+                BitrateMode::Vbr => args.extend_from_slice(&["-q:a", &vbr_quality]),
+                BitrateMode::Abr => args.extend_from_slice(&["-b:a", &bitrate, "-abr", "1"]),
+            }
+        }
+        "ogg" => {
+            args.extend_from_slice(&["-codec:a", "libvorbis"]);
+            match settings.mode {
+                BitrateMode::Cbr => args.extend_from_slice(&[
+                    "-b:a", &bitrate, "-minrate", &bitrate, "-maxrate", &bitrate,
+                ]),
+                BitrateMode::Vbr => args.extend_from_slice(&["-q:a", &vbr_quality]),
+                BitrateMode::Abr => args.extend_from_slice(&["-b:a", &bitrate]),
+            }
+        }
+        "flac" => {
+            args.extend_from_slice(&[
+                "-codec:a",
+                "flac",
+                "-compression_level",
+                "5",
+                "-exact_rice_parameters",
+                "1",
+            ]);
+            // Из s32 FFmpeg пишет 24-битный FLAC
+            args.extend_from_slice(match settings.bit_depth {
+                BitDepth::Int24 => &["-sample_fmt", "s32"],
+                _ => &["-sample_fmt", "s16"],
+            });
+        }
+        // PCM-энкодер под разрядность выбирает `discovery::ensure_format`
+        "wav" => args.extend_from_slice(&[
+            "-codec:a", codec,
+            // RF64, только если файл превысит 4 ГБ
+            "-rf64", "auto",
+        ]),
+        "opus" => args.extend_from_slice(&[
+            "-codec:a",
+            "libopus",
+            "-b:a",
+            &bitrate,
+            "-vbr",
+            match settings.opus.vbr {
+                OpusVbr::On => "on",
+                OpusVbr::Off => "off",
+                OpusVbr::Constrained => "constrained",
+            },
+            "-application",
+            match settings.opus.application {
+                OpusApplication::Audio => "audio",
+                OpusApplication::Voip => "voip",
+                OpusApplication::Lowdelay => "lowdelay",
+            },
+            // libopus работает только с 48 кГц
+            "-ar",
+            "48000",
+        ]),
+        "m4a" => {
+            args.extend_from_slice(&["-codec:a", codec]);
+            match (settings.mode, codec) {
+                (BitrateMode::Vbr, "libfdk_aac") => args.extend_from_slice(&["-vbr", &vbr_quality]),
+                (BitrateMode::Vbr, _) => args.extend_from_slice(&["-q:a", &vbr_quality]),
+                _ => args.extend_from_slice(&["-b:a", &bitrate]),
+            }
+            if codec == "libfdk_aac" {
+                args.extend_from_slice(&[
+                    "-profile:a",
+                    match settings.aac.profile {
+                        AacProfile::Lc => "aac_low",
+                        AacProfile::He => "aac_he",
+                    },
+                ]);
+            }
+            args.extend_from_slice(&["-movflags", movflags]);
+        }
+        "alac" => args.extend_from_slice(&[
+            "-codec:a",
+            "alac",
+            "-sample_fmt",
+            match settings.bit_depth {
+                BitDepth::Int24 => "s32p",
+                _ => "s16p",
+            },
+            "-movflags",
+            movflags,
+        ]),
+        other => anyhow::bail!("Неподдерживаемый формат: {}", other),
+    }
+    args.extend(metadata_args.iter().map(String::as_str));
+
+    args.extend_from_slice(&[
+        "-avoid_negative_ts",
+        "make_zero",
+        "-fflags",
+        "+genpts",
+        "-progress",
+        "pipe:1",
+        "-nostats",
+        "-y",
+    ]);
+
+    let mut child = Command::new(ffmpeg_path)
+        .args(&args)
+        .arg(output_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Не удалось запустить FFmpeg: {}", e))?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Нет stdin у FFmpeg"))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Нет stdout у FFmpeg"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Нет stderr у FFmpeg"))?;
+
+    // stdout и stderr читаются в отдельных потоках всё время кодирования:
+    // иначе FFmpeg заполнит буфер канала и остановится вместе с нами
+    let activity = Activity::new();
+    let (encoded_tx, encoded_rx) = mpsc::channel();
+    let progress_reader = {
+        let activity = activity.clone();
+        thread::spawn(move || read_encoder_progress(stdout, encoded_tx, activity))
+    };
+    let stderr_tail = Arc::new(Mutex::new(VecDeque::new()));
+    let stderr_reader = {
+        let stderr_tail = stderr_tail.clone();
+        let activity = activity.clone();
+        thread::spawn(move || drain_stderr(stderr, stderr_tail, activity))
+    };
+
+    let child = Arc::new(Mutex::new(child));
+    let watchdog = Watchdog::spawn(child.clone(), activity.clone(), cancel.clone());
+
+    let report_encoded = |encoded_seconds: f64| {
+        let fraction = (encoded_seconds / expected_duration.max(f64::EPSILON)).clamp(0.0, 1.0);
+        let prog = 80.0 + fraction as f32 * 19.0;
+        progress_callback(ExportProgress {
+            stage: ExportStage::Encoding,
+            progress: prog,
+            message: format!("{}: {:.1}%", settings.extension, fraction * 100.0),
+            record_name: Some(record_name.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
+        });
+    };
+
+    // ОПТИМИЗАЦИЯ: записываем большими чанками вместо по одному сэмплу
+    let chunk_size = sample_rate as usize * 4; // 4 секунды за раз
+    let mut write_error = None;
+    // Для целочисленных lossless форматов квантуем с дизерингом сами,
+    // FFmpeg затем лишь переводит точные значения в целые без округления
+    let mut quantizer = match settings.extension.as_str() {
+        "wav" | "flac" | "alac" => settings
+            .bit_depth
+            .integer_bits()
+            .map(|bits| Quantizer::new(bits, settings.dither)),
+        _ => None,
+    };
+
+    for chunk in samples.chunks(chunk_size) {
+        if cancel.is_cancelled() {
+            break;
+        }
+
+        // Собираем все сэмплы чанка в один буфер
+        let mut buffer = Vec::with_capacity(chunk.len() * 4); // 4 байта на float32
+        for &sample in chunk {
+            let sample = match quantizer.as_mut() {
+                Some(quantizer) => quantizer.quantize_to_float(sample),
+                None => sample,
+            };
+            buffer.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        // Записываем весь буфер за один вызов. Если FFmpeg завис, запись
+        // прервёт сторожевой поток, остановив процесс
+        if let Err(e) = stdin.write_all(&buffer) {
+            write_error = Some(e);
+            break;
+        }
+        activity.touch();
+
+        if let Some(encoded_seconds) = encoded_rx.try_iter().last() {
+            report_encoded(encoded_seconds);
+        }
+    }
+    drop(stdin);
+
+    if cancel.is_cancelled() {
+        // Останавливаем FFmpeg, не дожидаясь конца входных данных
+        let _ = child.lock().unwrap().kill();
+    }
+
+    // Дожидаемся, пока FFmpeg докодирует буферизованные данные
+    for encoded_seconds in encoded_rx {
+        report_encoded(encoded_seconds);
+    }
+    let _ = progress_reader.join();
+    let _ = stderr_reader.join();
+
+    let status = wait_child(&child);
+    let stalled = watchdog.stop();
+
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+
+    let tail = stderr_tail
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if stalled {
+        anyhow::bail!(
+            "FFmpeg не отвечает {} сек, процесс остановлен{}",
+            STALL_TIMEOUT.as_secs(),
+            if tail.is_empty() {
+                String::new()
+            } else {
+                format!(": {tail}")
+            }
+        );
+    }
+    let status = status.map_err(|e| anyhow::anyhow!("FFmpeg завершился с ошибкой: {}", e))?;
+    if !status.success() {
+        anyhow::bail!("FFmpeg: {}", tail);
+    }
+    if let Some(e) = write_error {
+        anyhow::bail!("Ошибка записи чанка: {}", e);
+    }
+    Ok(())
+}
+
+/// Сколько FFmpeg может молчать (нет прогресса, вывода и приёма данных),
+/// прежде чем процесс будет остановлен
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Сколько последних строк stderr сохраняется для сообщения об ошибке
+const STDERR_TAIL_LINES: usize = 40;
+
+/// Время последней активности FFmpeg
+#[derive(Clone)]
+struct Activity(Arc<Mutex<Instant>>);
+
+impl Activity {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    fn touch(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    fn idle(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+}
+
+/// Сторожевой поток: останавливает FFmpeg при отмене экспорта
+/// или если процесс перестал подавать признаки жизни
+struct Watchdog {
+    done: Arc<AtomicBool>,
+    handle: thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn spawn(child: Arc<Mutex<Child>>, activity: Activity, cancel: CancelToken) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    if cancel.is_cancelled() {
+                        let _ = child.lock().unwrap().kill();
+                        return false;
+                    }
+                    if activity.idle() > STALL_TIMEOUT {
+                        log::warn!(
+                            "FFmpeg не отвечает {} сек, останавливаем процесс",
+                            STALL_TIMEOUT.as_secs()
+                        );
+                        let _ = child.lock().unwrap().kill();
+                        return true;
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+                false
+            })
+        };
+        Self { done, handle }
+    }
+
+    /// Останавливает сторожа; возвращает `true`, если он убил зависший процесс
+    fn stop(self) -> bool {
+        self.done.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+/// Ждёт завершения процесса, не удерживая блокировку, чтобы сторож мог его убить
+fn wait_child(child: &Mutex<Child>) -> std::io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Временный файл, удаляемый вместе с владельцем
+struct TempFile(std::path::PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Читает вывод `-progress` FFmpeg (строки `key=value`) и отправляет
+/// закодированное время
+fn read_encoder_progress(stdout: impl Read, encoded_tx: mpsc::Sender<f64>, activity: Activity) {
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        activity.touch();
+        if let Some(seconds) = encoded_seconds(&line) {
+            // Получатель может уже не слушать, но stdout дочитываем до конца
+            let _ = encoded_tx.send(seconds);
+        }
+    }
+}
+
+/// Закодированное время из строки `-progress`. `out_time_us` есть только с
+/// FFmpeg 4.2; раньше то же значение в микросекундах приходит в `out_time_ms`
+/// и как `out_time=HH:MM:SS.micro`. Отрицательное время (задержка кодека)
+/// считается нулём.
+fn encoded_seconds(line: &str) -> Option<f64> {
+    let (key, value) = line.split_once('=')?;
+    let value = value.trim();
+    let seconds = match key {
+        "out_time_us" | "out_time_ms" => value.parse::<i64>().ok()? as f64 / 1_000_000.0,
+        "out_time" => {
+            let (negative, value) = match value.strip_prefix('-') {
+                Some(value) => (true, value),
+                None => (false, value),
+            };
+            let mut parts = value.split(':');
+            let hours = parts.next()?.parse::<f64>().ok()?;
+            let minutes = parts.next()?.parse::<f64>().ok()?;
+            let seconds = parts.next()?.parse::<f64>().ok()?;
+            let total = hours * 3600.0 + minutes * 60.0 + seconds;
+            if negative {
+                -total
+            } else {
+                total
+            }
+        }
+        _ => return None,
+    };
+    Some(seconds.max(0.0))
+}
+
+/// Читает stderr до конца, сохраняя последние строки для сообщения об ошибке
+fn drain_stderr(stderr: impl Read, tail: Arc<Mutex<VecDeque<String>>>, activity: Activity) {
+    for line in BufReader::new(stderr).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+        activity.touch();
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        let mut tail = tail.lock().unwrap();
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Блоки `-progress` FFmpeg 6.1 и 4.0 (до появления `out_time_us`)
+    const PROGRESS_6_1: &str = "bitrate= 256.0kbits/s\n\
+                                total_size=65580\n\
+                                out_time_us=2048000\n\
+                                out_time_ms=2048000\n\
+                                out_time=00:00:02.048000\n\
+                                progress=continue\n";
+    const PROGRESS_4_0: &str = "total_size=65580\n\
+                                out_time_ms=3723500000\n\
+                                out_time=01:02:03.500000\n\
+                                progress=end\n";
+
+    fn times(block: &str) -> Vec<f64> {
+        block.lines().filter_map(encoded_seconds).collect()
+    }
+
+    #[test]
+    fn progress_time_from_every_version() {
+        assert_eq!(times(PROGRESS_6_1), [2.048, 2.048, 2.048]);
+        assert_eq!(times(PROGRESS_4_0), [3723.5, 3723.5]);
+    }
+
+    #[test]
+    fn negative_or_missing_time() {
+        assert_eq!(encoded_seconds("out_time_us=-23220"), Some(0.0));
+        assert_eq!(encoded_seconds("out_time=-00:00:00.023220"), Some(0.0));
+        assert_eq!(encoded_seconds("out_time_ms=N/A"), None);
+        assert_eq!(encoded_seconds("progress=continue"), None);
+    }
+}
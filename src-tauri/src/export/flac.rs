@@ -0,0 +1,586 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Размер блока FLAC в сэмплах (как у `flac -5`)
+const BLOCK_SIZE: usize = 4096;
+/// Максимальный порядок разбиения остатков на Rice-партиции
+const MAX_PARTITION_ORDER: u32 = 4;
+/// Rice-параметры 4-битного метода кодирования (15 — escape, не используем)
+const MAX_RICE_PARAM: u32 = 14;
+/// Строка производителя в блоке VORBIS_COMMENT
+const VENDOR: &str = "GenSoundAD";
+/// Rice-параметры 5-битного метода (RICE2) для 24-битных остатков
+const MAX_RICE2_PARAM: u32 = 30;
+
+/// Потоковый кодировщик FLAC: 16 или 24 бита, моно, фиксированные предсказатели.
+///
+/// Сжимает хуже libFLAC (без LPC), зато не требует внешних библиотек.
+/// MD5 в STREAMINFO не считается (нули допускаются спецификацией).
+pub struct FlacWriter {
+    out: BufWriter<File>,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    has_comments: bool,
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_samples: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl FlacWriter {
+    /// `comments` — теги в виде `KEY=value` для блока VORBIS_COMMENT
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        bits_per_sample: u32,
+        comments: &[String],
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            matches!(bits_per_sample, 16 | 24),
+            "FLAC: неподдерживаемая разрядность {bits_per_sample} бит"
+        );
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+        let mut writer = Self {
+            out: BufWriter::new(file),
+            sample_rate,
+            bits_per_sample,
+            has_comments: !comments.is_empty(),
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+        };
+        // STREAMINFO перезаписывается в `finalize`, когда известны длина и размеры кадров
+        writer.out.write_all(b"fLaC")?;
+        writer.out.write_all(&writer.stream_info())?;
+        if writer.has_comments {
+            writer.out.write_all(&vorbis_comment(comments))?;
+        }
+        Ok(writer)
+    }
+
+    /// Сэмплы в диапазоне разрядности, заданной в `create`
+    pub fn write_samples(&mut self, samples: &[i32]) -> Result<()> {
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() == BLOCK_SIZE {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.flush_block()?;
+        }
+        let stream_info = self.stream_info();
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&stream_info)?;
+        self.out.flush().context("Failed to finalize FLAC file")?;
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        let frame = encode_frame(
+            &self.pending,
+            self.frame_number,
+            self.sample_rate,
+            self.bits_per_sample,
+        );
+        self.out.write_all(&frame)?;
+
+        self.min_frame_size = self.min_frame_size.min(frame.len() as u32);
+        self.max_frame_size = self.max_frame_size.max(frame.len() as u32);
+        self.total_samples += self.pending.len() as u64;
+        self.frame_number += 1;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Заголовок метаданных и блок STREAMINFO (последний блок метаданных)
+    fn stream_info(&self) -> Vec<u8> {
+        let mut bits = BitWriter::new();
+        bits.write(!self.has_comments as u64, 1); // последний блок метаданных
+        bits.write(0, 7); // STREAMINFO
+        bits.write(34, 24);
+
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        let min_frame = if self.min_frame_size == u32::MAX {
+            0
+        } else {
+            self.min_frame_size
+        };
+        bits.write(min_frame as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(0, 3); // каналов - 1
+        bits.write((self.bits_per_sample - 1) as u64, 5);
+        bits.write(self.total_samples, 36);
+        for _ in 0..4 {
+            bits.write(0, 32); // MD5 не вычисляется
+        }
+        bits.into_bytes()
+    }
+}
+
+/// Блок VORBIS_COMMENT (последний блок метаданных); длины в нём little-endian
+fn vorbis_comment(comments: &[String]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    body.extend_from_slice(VENDOR.as_bytes());
+    body.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        body.extend_from_slice(comment.as_bytes());
+    }
+
+    let mut block = vec![0x80 | 4];
+    block.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    block.extend(body);
+    block
+}
+
+/// Кодирует один кадр: заголовок, подкадр с лучшим предсказателем, CRC-16
+fn encode_frame(block: &[i32], frame_number: u64, sample_rate: u32, bps: u32) -> Vec<u8> {
+    let mut bits = BitWriter::new();
+    bits.write(0b11_1111_1111_1110, 14); // синхрокод
+    bits.write(0, 1);
+    bits.write(0, 1); // фиксированный размер блока
+    bits.write(0b0111, 4); // размер блока - 1 в 16 битах после номера кадра
+    bits.write(sample_rate_code(sample_rate) as u64, 4);
+    bits.write(0, 4); // моно
+    bits.write(if bps == 24 { 0b110 } else { 0b100 }, 3); // разрядность
+    bits.write(0, 1);
+    write_utf8_number(&mut bits, frame_number);
+    bits.write((block.len() - 1) as u64, 16);
+    if sample_rate_code(sample_rate) == 0b1100 {
+        bits.write((sample_rate / 1000) as u64, 8);
+    }
+    let header_crc = crc8(bits.bytes());
+    bits.write(header_crc as u64, 8);
+
+    write_subframe(&mut bits, block, bps);
+
+    bits.align();
+    let frame_crc = crc16(bits.bytes());
+    bits.write(frame_crc as u64, 16);
+    bits.into_bytes()
+}
+
+fn sample_rate_code(sample_rate: u32) -> u32 {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        // Частота в кГц в 8 битах после размера блока
+        _ => 0b1100,
+    }
+}
+
+fn write_subframe(bits: &mut BitWriter, block: &[i32], bps: u32) {
+    if block.iter().all(|&s| s == block[0]) {
+        bits.write(0, 1);
+        bits.write(0b000000, 6); // CONSTANT
+        bits.write(0, 1);
+        bits.write_signed(block[0] as i64, bps);
+        return;
+    }
+
+    // 24-битные остатки не помещаются в 4-битные Rice-параметры
+    let rice = if bps > 16 {
+        RiceMethod::Rice2
+    } else {
+        RiceMethod::Rice
+    };
+    let verbatim_bits = block.len() as u64 * bps as u64;
+    let best = (0..=4usize)
+        .filter(|&order| order < block.len())
+        .map(|order| {
+            let residual = fixed_residual(block, order);
+            let (partition_order, params, residual_bits) =
+                choose_partitions(&residual, order, rice);
+            let bits = order as u64 * bps as u64 + residual_bits;
+            (bits, order, residual, partition_order, params)
+        })
+        .min_by_key(|(bits, ..)| *bits);
+
+    match best {
+        Some((bits_needed, order, residual, partition_order, params))
+            if bits_needed < verbatim_bits =>
+        {
+            bits.write(0, 1);
+            bits.write(0b001000 | order as u64, 6); // FIXED
+            bits.write(0, 1);
+            for &warmup in &block[..order] {
+                bits.write_signed(warmup as i64, bps);
+            }
+
+            bits.write(rice as u64, 2);
+            bits.write(partition_order as u64, 4);
+            let partition_len = block.len() >> partition_order;
+            let mut offset = 0;
+            for (p, &param) in params.iter().enumerate() {
+                let len = if p == 0 {
+                    partition_len - order
+                } else {
+                    partition_len
+                };
+                bits.write(param as u64, rice.param_bits());
+                for &r in &residual[offset..offset + len] {
+                    bits.write_rice(zigzag(r), param);
+                }
+                offset += len;
+            }
+        }
+        _ => {
+            bits.write(0, 1);
+            bits.write(0b000001, 6); // VERBATIM
+            bits.write(0, 1);
+            for &s in block {
+                bits.write_signed(s as i64, bps);
+            }
+        }
+    }
+}
+
+/// Метод кодирования остатков: ширина Rice-параметра 4 или 5 бит
+#[derive(Clone, Copy)]
+enum RiceMethod {
+    Rice = 0b00,
+    Rice2 = 0b01,
+}
+
+impl RiceMethod {
+    fn param_bits(self) -> u32 {
+        match self {
+            Self::Rice => 4,
+            Self::Rice2 => 5,
+        }
+    }
+
+    fn max_param(self) -> u32 {
+        match self {
+            Self::Rice => MAX_RICE_PARAM,
+            Self::Rice2 => MAX_RICE2_PARAM,
+        }
+    }
+}
+
+/// Остатки фиксированного предсказателя порядка 0..=4
+fn fixed_residual(block: &[i32], order: usize) -> Vec<i64> {
+    (order..block.len())
+        .map(|n| {
+            let x = |k: usize| block[n - k] as i64;
+            match order {
+                0 => x(0),
+                1 => x(0) - x(1),
+                2 => x(0) - 2 * x(1) + x(2),
+                3 => x(0) - 3 * x(1) + 3 * x(2) - x(3),
+                _ => x(0) - 4 * x(1) + 6 * x(2) - 4 * x(3) + x(4),
+            }
+        })
+        .collect()
+}
+
+/// Подбирает порядок разбиения и Rice-параметры; возвращает оценку размера в битах
+fn choose_partitions(
+    residual: &[i64],
+    predictor_order: usize,
+    rice: RiceMethod,
+) -> (u32, Vec<u32>, u64) {
+    let block_len = residual.len() + predictor_order;
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        let partition_len = block_len >> partition_order;
+        if partition_len << partition_order != block_len || partition_len <= predictor_order {
+            break;
+        }
+
+        let mut params = Vec::with_capacity(partitions);
+        let mut total_bits = 6u64; // метод и порядок разбиения
+        let mut offset = 0;
+        for p in 0..partitions {
+            let len = if p == 0 {
+                partition_len - predictor_order
+            } else {
+                partition_len
+            };
+            let part = &residual[offset..offset + len];
+            offset += len;
+
+            let (param, bits) = best_rice_param(part, rice.max_param());
+            params.push(param);
+            total_bits += rice.param_bits() as u64 + bits;
+        }
+
+        let better = match &best {
+            Some((_, _, bits)) => total_bits < *bits,
+            None => true,
+        };
+        if better {
+            best = Some((partition_order, params, total_bits));
+        }
+    }
+
+    best.unwrap_or((0, vec![0], u64::MAX / 2))
+}
+
+/// Rice-параметр с минимальным размером партиции (точный подсчёт вокруг оценки по среднему)
+fn best_rice_param(part: &[i64], max_param: u32) -> (u32, u64) {
+    if part.is_empty() {
+        return (0, 0);
+    }
+    let sum: u64 = part.iter().map(|&r| zigzag(r)).sum();
+    let mean = sum / part.len() as u64;
+    let estimate = (64 - mean.leading_zeros()).min(max_param);
+
+    (estimate.saturating_sub(1)..=(estimate + 1).min(max_param))
+        .map(|k| {
+            let bits = part
+                .iter()
+                .map(|&r| (zigzag(r) >> k) + 1 + k as u64)
+                .sum::<u64>();
+            (k, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, 0))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Номер кадра в "UTF-8" кодировке FLAC
+fn write_utf8_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+    let mut continuation = 1;
+    while value >= 1u64 << (5 * continuation + 6) {
+        continuation += 1;
+    }
+    let lead_bits = 6 - continuation;
+    let lead_mask = (0xFFu64 << (7 - continuation)) & 0xFF;
+    bits.write(
+        lead_mask | ((value >> (6 * continuation)) & ((1 << lead_bits) - 1)),
+        8,
+    );
+    for i in (0..continuation).rev() {
+        bits.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Запись битового потока старшими битами вперёд
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    filled: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            filled: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, width: u32) {
+        let mut remaining = width;
+        while remaining > 0 {
+            let take = remaining.min(32);
+            let chunk = (value >> (remaining - take)) & ((1u64 << take) - 1);
+            self.acc = (self.acc << take) | chunk;
+            self.filled += take;
+            remaining -= take;
+            while self.filled >= 8 {
+                self.filled -= 8;
+                self.bytes.push((self.acc >> self.filled) as u8);
+            }
+            self.acc &= (1u64 << self.filled) - 1;
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, width: u32) {
+        self.write(value as u64 & ((1u64 << width) - 1), width);
+    }
+
+    fn write_rice(&mut self, value: u64, param: u32) {
+        let mut quotient = value >> param;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        // `quotient` нулей и завершающая единица
+        self.write(1, quotient as u32 + 1);
+        self.write(value & ((1u64 << param) - 1), param);
+    }
+
+    fn align(&mut self) {
+        if self.filled > 0 {
+            self.write(0, 8 - self.filled);
+        }
+    }
+
+    /// Уже записанные целые байты (для CRC заголовка и кадра)
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AudioProcessor;
+
+    #[test]
+    fn bit_writer_packs_msb_first() {
+        let mut bits = BitWriter::new();
+        bits.write(0b101, 3);
+        bits.write(0b11111, 5);
+        bits.write_signed(-1, 4);
+        bits.write(0x12_3456_789A, 40);
+        assert_eq!(
+            bits.into_bytes(),
+            [0xBF, 0xF1, 0x23, 0x45, 0x67, 0x89, 0xA0]
+        );
+    }
+
+    #[test]
+    fn rice_codes() {
+        let mut bits = BitWriter::new();
+        // 5 = 0b10 << 1 | 1: два нуля, единица, остаток 1
+        bits.write_rice(5, 1);
+        assert_eq!(bits.into_bytes(), [0b0011_0000]);
+
+        // Частное больше 32 пишется несколькими порциями нулей
+        let mut bits = BitWriter::new();
+        bits.write_rice(70, 0);
+        let bytes = bits.into_bytes();
+        assert_eq!(bytes.len(), 9);
+        assert!(bytes[..8].iter().all(|&b| b == 0));
+        assert_eq!(bytes[8], 0b0000_0010);
+
+        assert_eq!([0, -1, 1, -2].map(zigzag), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn partitions_follow_residual_energy() {
+        // Шум в первой половине и тишина во второй: выгоднее разбить
+        let residual: Vec<i64> = (0..BLOCK_SIZE as i64)
+            .map(|i| {
+                if i < 2048 {
+                    (i * 7919 % 2001) - 1000
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let (order, params, _) = choose_partitions(&residual, 0, RiceMethod::Rice);
+        assert!(order >= 1);
+        assert!(params[0] > 0);
+        assert_eq!(*params.last().unwrap(), 0);
+
+        // Тишина: разбиение только добавляет параметры
+        let (order, params, bits) = choose_partitions(&[0; BLOCK_SIZE], 0, RiceMethod::Rice);
+        assert_eq!((order, params), (0, vec![0]));
+        assert_eq!(bits, 6 + 4 + BLOCK_SIZE as u64);
+
+        // Блок из 4100 сэмплов делится на 4 партиции, но не на 8
+        let (order, params, _) = choose_partitions(&vec![1000; 4098], 2, RiceMethod::Rice);
+        assert!(order <= 2);
+        assert_eq!(params.len(), 1 << order);
+    }
+
+    fn round_trip(bits_per_sample: u32) {
+        let full_scale = 1i64 << (bits_per_sample - 1);
+        let samples: Vec<i32> = (0..10_000i64)
+            .map(|i| {
+                let sine = (i as f64 * 0.05).sin() * 0.6 * full_scale as f64;
+                let noise = (i * 7919 % 201 - 100) * full_scale / 3000;
+                (sine as i64 + noise) as i32
+            })
+            .chain([(full_scale - 1) as i32, -full_scale as i32, 0])
+            .collect();
+
+        let path =
+            std::env::temp_dir().join(format!("gensoundad_flac_test_{bits_per_sample}.flac"));
+        let mut writer =
+            FlacWriter::create(&path, 44100, bits_per_sample, &["TITLE=test".into()]).unwrap();
+        writer.write_samples(&samples).unwrap();
+        writer.finalize().unwrap();
+
+        let decoded = AudioProcessor::new()
+            .decode_audio_file(&path.to_string_lossy())
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(decoded.len(), samples.len());
+        for (i, (&expected, &got)) in samples.iter().zip(&decoded).enumerate() {
+            let got = (got as f64 * full_scale as f64).round() as i32;
+            assert_eq!(got, expected, "сэмпл {i}");
+        }
+    }
+
+    #[test]
+    fn round_trip_16_bit() {
+        round_trip(16);
+    }
+
+    #[test]
+    fn round_trip_24_bit() {
+        round_trip(24);
+    }
+}
@@ -0,0 +1,425 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::audio::*;
+use crate::export::progress::throttled;
+use crate::export::{export_records, failure_progress, Encoder, ProgressCallback};
+
+/// Сколько завершённых заданий хранится для `list_jobs`; более старые удаляются
+pub const MAX_FINISHED_JOBS: usize = 50;
+
+/// Выбирает кодировщик при запуске задания (поиск FFmpeg может занять время)
+pub type EncoderResolver = Box<dyn FnOnce() -> anyhow::Result<Encoder> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// Задание экспорта в очереди бэкенда. Хранится в состоянии приложения,
+/// поэтому переживает перезагрузку окна.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub id: String,
+    pub record_names: Vec<String>,
+    pub output_dir: String,
+    pub state: JobState,
+    pub stage: Option<ExportStage>,
+    pub progress: f32, // 0.0 - 100.0
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub output_paths: Vec<String>,
+    pub results: Vec<RecordExportResult>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    record_progress: HashMap<String, f32>,
+}
+
+struct PendingJob {
+    id: String,
+    request: ExportRequest,
+    record_names: Vec<String>,
+    output_dir: PathBuf,
+    encoder: EncoderResolver,
+    max_parallel: usize,
+    progress_callback: ProgressCallback,
+}
+
+struct QueueInner {
+    jobs: Vec<ExportJob>,
+    pending: VecDeque<PendingJob>,
+    /// Флаги отмены заданий очереди и прямых экспортов (`export_audio`, `export_all`)
+    cancels: HashMap<String, CancelToken>,
+    running: usize,
+    max_running: usize,
+}
+
+impl QueueInner {
+    /// Удаляет самые давно завершённые задания сверх `MAX_FINISHED_JOBS`
+    fn prune_finished(&mut self) {
+        let mut finished: Vec<(DateTime<Utc>, String)> = self
+            .jobs
+            .iter()
+            .filter(|j| j.state.is_finished())
+            .map(|j| (j.finished_at.unwrap_or(j.created_at), j.id.clone()))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED_JOBS;
+        let stale: Vec<String> = finished
+            .into_iter()
+            .take(excess)
+            .map(|(_, id)| id)
+            .collect();
+        self.jobs.retain(|j| !stale.contains(&j.id));
+    }
+}
+
+/// Очередь заданий экспорта: задания выполняются по порядку,
+/// не более `max_running` одновременно
+#[derive(Clone)]
+pub struct JobQueue {
+    inner: Arc<Mutex<QueueInner>>,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl JobQueue {
+    pub fn new(max_running: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(QueueInner {
+                jobs: Vec::new(),
+                pending: VecDeque::new(),
+                cancels: HashMap::new(),
+                running: 0,
+                max_running: max_running.max(1),
+            })),
+        }
+    }
+
+    /// Ставит экспорт в очередь и сразу возвращает id задания
+    pub fn submit(
+        &self,
+        request: ExportRequest,
+        record_names: Vec<String>,
+        output_dir: PathBuf,
+        encoder: EncoderResolver,
+        max_parallel: usize,
+        progress_callback: ProgressCallback,
+    ) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.jobs.push(ExportJob {
+                id: id.clone(),
+                record_names: record_names.clone(),
+                output_dir: output_dir.to_string_lossy().to_string(),
+                state: JobState::Queued,
+                stage: None,
+                progress: 0.0,
+                message: None,
+                created_at: Utc::now(),
+                started_at: None,
+                finished_at: None,
+                output_paths: Vec::new(),
+                results: Vec::new(),
+                error: None,
+                record_progress: HashMap::new(),
+            });
+            inner.cancels.insert(id.clone(), CancelToken::new());
+            inner.pending.push_back(PendingJob {
+                id: id.clone(),
+                request,
+                record_names,
+                output_dir,
+                encoder,
+                max_parallel,
+                progress_callback,
+            });
+        }
+
+        log::info!("Задание экспорта {id} поставлено в очередь");
+        self.pump();
+        id
+    }
+
+    pub fn list(&self) -> Vec<ExportJob> {
+        self.inner.lock().unwrap().jobs.clone()
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<ExportJob> {
+        let inner = self.inner.lock().unwrap();
+        inner.jobs.iter().find(|j| j.id == job_id).cloned()
+    }
+
+    /// Отменяет задание: ожидающее снимается с очереди, выполняющееся прерывается
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(pos) = inner.pending.iter().position(|j| j.id == job_id) {
+            inner.pending.remove(pos);
+            inner.cancels.remove(job_id);
+            if let Some(job) = inner.jobs.iter_mut().find(|j| j.id == job_id) {
+                job.state = JobState::Cancelled;
+                job.finished_at = Some(Utc::now());
+            }
+            inner.prune_finished();
+            return true;
+        }
+
+        match inner.cancels.get(job_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Регистрирует прямой экспорт вне очереди, чтобы его можно было отменить.
+    /// Id, занятый другим заданием, отклоняется: иначе его отмена и
+    /// завершение затронули бы чужое задание.
+    pub fn register(&self, job_id: &str) -> anyhow::Result<CancelToken> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.cancels.contains_key(job_id) || inner.jobs.iter().any(|j| j.id == job_id) {
+            anyhow::bail!("Задание экспорта {job_id} уже существует");
+        }
+        let cancel = CancelToken::new();
+        inner.cancels.insert(job_id.to_string(), cancel.clone());
+        Ok(cancel)
+    }
+
+    pub fn unregister(&self, job_id: &str) {
+        self.inner.lock().unwrap().cancels.remove(job_id);
+    }
+
+    pub fn set_max_running(&self, max_running: usize) {
+        self.inner.lock().unwrap().max_running = max_running.max(1);
+        self.pump();
+    }
+
+    /// Запускает ожидающие задания, пока есть свободные слоты
+    fn pump(&self) {
+        loop {
+            let next = {
+                let mut inner = self.inner.lock().unwrap();
+                if inner.running >= inner.max_running {
+                    None
+                } else if let Some(job) = inner.pending.pop_front() {
+                    inner.running += 1;
+                    let cancel = inner.cancels.get(&job.id).cloned().unwrap_or_default();
+                    if let Some(state) = inner.jobs.iter_mut().find(|j| j.id == job.id) {
+                        state.state = JobState::Running;
+                        state.started_at = Some(Utc::now());
+                    }
+                    Some((job, cancel))
+                } else {
+                    None
+                }
+            };
+
+            let Some((job, cancel)) = next else {
+                break;
+            };
+            let queue = self.clone();
+            tokio::spawn(async move { queue.run(job, cancel).await });
+        }
+    }
+
+    async fn run(self, job: PendingJob, cancel: CancelToken) {
+        let job_id = job.id.clone();
+        log::info!("Задание экспорта {job_id} запущено");
+
+        let progress_callback: ProgressCallback = {
+            let queue = self.clone();
+            let job_id = job_id.clone();
+            let forward = throttled(job.progress_callback.clone());
+            Arc::new(move |mut progress: ExportProgress| {
+                progress.job_id = Some(job_id.clone());
+                queue.record_progress(&job_id, &progress);
+                forward(progress);
+            })
+        };
+
+        let encoder = tokio::task::spawn_blocking(job.encoder)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|encoder| encoder);
+        let results = match encoder {
+            Ok(encoder) => {
+                export_records(
+                    job.request,
+                    job.record_names,
+                    job.output_dir,
+                    encoder,
+                    job.max_parallel,
+                    cancel.clone(),
+                    progress_callback,
+                )
+                .await
+            }
+            Err(e) => {
+                log::error!("Задание экспорта {job_id}: {e:#}");
+                progress_callback(failure_progress(&e, None));
+                job.record_names
+                    .iter()
+                    .map(|record_name| RecordExportResult {
+                        record_name: record_name.clone(),
+                        output_path: None,
+                        error: Some(format!("{e:#}")),
+                        verification: Vec::new(),
+                    })
+                    .collect()
+            }
+        };
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.running -= 1;
+            inner.cancels.remove(&job_id);
+
+            if let Some(state) = inner.jobs.iter_mut().find(|j| j.id == job_id) {
+                let errors: Vec<String> = results
+                    .iter()
+                    .filter_map(|r| {
+                        r.error
+                            .as_ref()
+                            .map(|e| format!("{}: {}", r.record_name, e))
+                    })
+                    .collect();
+
+                state.state = if cancel.is_cancelled() {
+                    JobState::Cancelled
+                } else if errors.is_empty() {
+                    JobState::Completed
+                } else {
+                    JobState::Failed
+                };
+                if state.state == JobState::Completed {
+                    state.progress = 100.0;
+                }
+                state.output_paths = results
+                    .iter()
+                    .filter_map(|r| r.output_path.clone())
+                    .collect();
+                state.error = (!errors.is_empty()).then(|| errors.join("\n"));
+                state.results = results;
+                state.finished_at = Some(Utc::now());
+                log::info!("Задание экспорта {job_id} завершено: {:?}", state.state);
+            }
+            inner.prune_finished();
+        }
+
+        self.pump();
+    }
+
+    /// Обновляет прогресс задания: среднее по всем его записям
+    fn record_progress(&self, job_id: &str, progress: &ExportProgress) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(job) = inner.jobs.iter_mut().find(|j| j.id == job_id) else {
+            return;
+        };
+
+        match &progress.record_name {
+            Some(record_name) => {
+                job.record_progress
+                    .insert(record_name.clone(), progress.progress);
+            }
+            // Общий этап (декодирование источников) относится ко всем записям
+            None => {
+                for record_name in &job.record_names {
+                    job.record_progress
+                        .insert(record_name.clone(), progress.progress);
+                }
+            }
+        }
+
+        let total: f32 = job.record_progress.values().sum();
+        job.progress = total / job.record_names.len().max(1) as f32;
+        job.stage = Some(progress.stage);
+        job.message = Some(progress.message.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn job(id: usize, state: JobState) -> ExportJob {
+        let created_at = Utc::now() - Duration::minutes(1000 - id as i64);
+        ExportJob {
+            id: id.to_string(),
+            record_names: Vec::new(),
+            output_dir: String::new(),
+            state,
+            stage: None,
+            progress: 0.0,
+            message: None,
+            created_at,
+            started_at: None,
+            finished_at: state.is_finished().then_some(created_at),
+            output_paths: Vec::new(),
+            results: Vec::new(),
+            error: None,
+            record_progress: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn prune_keeps_newest_finished_and_all_active() {
+        let mut inner = QueueInner {
+            jobs: (0..MAX_FINISHED_JOBS + 10)
+                .map(|i| job(i, JobState::Completed))
+                .chain([job(900, JobState::Queued), job(901, JobState::Running)])
+                .collect(),
+            pending: VecDeque::new(),
+            cancels: HashMap::new(),
+            running: 1,
+            max_running: 1,
+        };
+        inner.prune_finished();
+
+        assert_eq!(inner.jobs.len(), MAX_FINISHED_JOBS + 2);
+        assert_eq!(inner.jobs[0].id, "10");
+        assert!(inner.jobs.iter().any(|j| j.id == "900"));
+        assert!(inner.jobs.iter().any(|j| j.id == "901"));
+    }
+
+    #[test]
+    fn duplicate_job_id_is_rejected() {
+        let queue = JobQueue::new(1);
+        let cancel = queue.register("direct").unwrap();
+        assert!(queue.register("direct").is_err());
+
+        // Отмена по id доходит до первого задания
+        assert!(queue.cancel("direct"));
+        assert!(cancel.check().is_err());
+
+        queue.unregister("direct");
+        assert!(queue.register("direct").is_ok());
+    }
+}
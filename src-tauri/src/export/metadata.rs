@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+
+use crate::audio::zone::ProjectZone;
+use crate::audio::*;
+
+/// Метаданные, записываемые в экспортируемый файл
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Теги в порядке записи: стандартные ключи (`title`, `artist`, `album`,
+    /// `date`, `comment`) и произвольные поля
+    pub tags: Vec<(String, String)>,
+    /// Главы по возрастанию начала
+    pub chapters: Vec<Chapter>,
+}
+
+/// Глава файла: одно прозвучавшее объявление
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl Chapter {
+    pub fn from_record(record: &RenderedRecord) -> Vec<Self> {
+        record
+            .markers
+            .iter()
+            .map(|marker| Self {
+                title: marker.title.clone(),
+                start_ms: record.ms(marker.range.start),
+                end_ms: record.ms(marker.range.end),
+            })
+            .collect()
+    }
+
+    /// Главы, попадающие в отрезок записи, со временем от его начала
+    pub fn within(chapters: &[Self], start_ms: u64, end_ms: u64) -> Vec<Self> {
+        chapters
+            .iter()
+            .filter(|chapter| chapter.start_ms < end_ms && chapter.end_ms > start_ms)
+            .map(|chapter| Self {
+                title: chapter.title.clone(),
+                start_ms: chapter.start_ms.max(start_ms) - start_ms,
+                end_ms: chapter.end_ms.min(end_ms) - start_ms,
+            })
+            .collect()
+    }
+}
+
+impl Metadata {
+    /// Заполняет шаблоны тегов из настроек данными запроса
+    pub fn resolve(request: &ExportRequest, record_name: &str) -> Result<Self> {
+        let settings = &request.settings.tags;
+        if !settings.enabled {
+            return Ok(Self::default());
+        }
+
+        let time_record = request
+            .time_of_records
+            .get(record_name)
+            .context("Time record not found")?;
+        let zone = ProjectZone::from_name(request.time_zone.as_deref())?;
+        let start = zone.local_datetime(&time_record.start);
+        let end = zone.local_datetime(&time_record.end);
+
+        let fill = |template: &str| {
+            template
+                .replace("{record}", record_name)
+                .replace("{project}", request.project_name.as_deref().unwrap_or(""))
+                .replace("{date}", &start.format("%Y-%m-%d").to_string())
+                .replace("{year}", &start.format("%Y").to_string())
+                .replace("{start}", &start.format("%H:%M").to_string())
+                .replace("{end}", &end.format("%H:%M").to_string())
+                .trim()
+                .to_string()
+        };
+
+        let standard = [
+            ("title", &settings.title),
+            ("artist", &settings.artist),
+            ("album", &settings.album),
+            ("date", &settings.date),
+            ("comment", &settings.comment),
+        ];
+        let tags = standard
+            .into_iter()
+            .filter_map(|(key, template)| Some((key.to_string(), template.as_deref()?)))
+            .chain(
+                settings
+                    .custom
+                    .iter()
+                    .map(|(key, template)| (key.trim().to_string(), template.as_str())),
+            )
+            .map(|(key, template)| (key, fill(template)))
+            .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+            .collect();
+
+        Ok(Self {
+            tags,
+            chapters: Vec::new(),
+        })
+    }
+
+    /// Пары `-metadata` для FFmpeg: он сам переводит стандартные ключи в
+    /// ID3v2, Vorbis comments, атомы MP4 и LIST/INFO
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .flat_map(|(key, value)| ["-metadata".to_string(), format!("{key}={value}")])
+            .collect()
+    }
+
+    /// Есть ли поля кроме стандартных (для MP4 их нужно разрешить явно)
+    pub fn has_custom(&self) -> bool {
+        self.tags.iter().any(|(key, _)| riff_info_id(key).is_none())
+    }
+
+    /// Файл FFMETADATA1 с главами для `-map_chapters`
+    pub fn ffmetadata(&self) -> String {
+        let mut text = String::from(";FFMETADATA1\n");
+        for chapter in &self.chapters {
+            text.push_str(&format!(
+                "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+                chapter.start_ms,
+                chapter.end_ms,
+                escape_ffmetadata(&chapter.title)
+            ));
+        }
+        text
+    }
+
+    /// Vorbis comments (`TITLE=...`) для FLAC, включая главы в формате
+    /// CHAPTERxxx / CHAPTERxxxNAME
+    pub fn vorbis_comments(&self) -> Vec<String> {
+        let tags = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={value}", key.to_uppercase()));
+        let chapters = self.chapters.iter().enumerate().flat_map(|(i, chapter)| {
+            [
+                format!("CHAPTER{:03}={}", i + 1, clock(chapter.start_ms)),
+                format!("CHAPTER{:03}NAME={}", i + 1, chapter.title),
+            ]
+        });
+        tags.chain(chapters).collect()
+    }
+
+    /// Поля LIST/INFO для WAV; произвольные поля в INFO не предусмотрены
+    pub fn riff_info(&self) -> Vec<([u8; 4], &str)> {
+        self.tags
+            .iter()
+            .filter_map(|(key, value)| Some((riff_info_id(key)?, value.as_str())))
+            .collect()
+    }
+}
+
+/// Время ЧЧ:ММ:СС.ммм (главы Vorbis, смещения в плейлистах)
+pub(super) fn clock(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// Экранирование спецсимволов FFMETADATA1
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn riff_info_id(key: &str) -> Option<[u8; 4]> {
+    match key {
+        "title" => Some(*b"INAM"),
+        "artist" => Some(*b"IART"),
+        "album" => Some(*b"IPRD"),
+        "date" => Some(*b"ICRD"),
+        "comment" => Some(*b"ICMT"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(tags: serde_json::Value) -> ExportRequest {
+        serde_json::from_value(serde_json::json!({
+            "sources": [],
+            "arrangements": {},
+            "time_of_records": {
+                "monday": {"start": "2024-09-02T05:00:00Z", "end": "2024-09-02T06:30:00Z"}
+            },
+            "time_zone": "Europe/Moscow",
+            "settings": {"extension": "mp3", "bitrate": 192, "tags": tags},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn templates_are_filled_in_project_zone() {
+        let request = request(serde_json::json!({
+            "title": "{record} {start}–{end}",
+            "album": "{project}",
+            "date": "{date}",
+            "comment": "  ",
+            "custom": {"ORGANIZATION": "Школа {year}", " ": "пустой ключ"},
+        }));
+        let metadata = Metadata::resolve(&request, "monday").unwrap();
+        let tags: Vec<(&str, &str)> = metadata
+            .tags
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        // Пустой проект, пробельный комментарий и пустой ключ не пишутся
+        assert_eq!(
+            tags,
+            vec![
+                ("title", "monday 08:00–09:30"),
+                ("date", "2024-09-02"),
+                ("ORGANIZATION", "Школа 2024"),
+            ]
+        );
+        assert_eq!(
+            metadata.ffmpeg_args(),
+            vec![
+                "-metadata",
+                "title=monday 08:00–09:30",
+                "-metadata",
+                "date=2024-09-02",
+                "-metadata",
+                "ORGANIZATION=Школа 2024",
+            ]
+        );
+        assert!(metadata.has_custom());
+        assert_eq!(
+            metadata.riff_info(),
+            vec![(*b"INAM", "monday 08:00–09:30"), (*b"ICRD", "2024-09-02")]
+        );
+    }
+
+    #[test]
+    fn disabled_tags_are_empty() {
+        let request = request(serde_json::json!({"enabled": false}));
+        let metadata = Metadata::resolve(&request, "monday").unwrap();
+        assert!(metadata.tags.is_empty());
+        assert!(!metadata.has_custom());
+    }
+
+    fn chapters() -> Metadata {
+        Metadata {
+            tags: vec![("title".into(), "monday".into())],
+            chapters: vec![
+                Chapter {
+                    title: "Звонок=1; #2".into(),
+                    start_ms: 0,
+                    end_ms: 1500,
+                },
+                Chapter {
+                    title: "Гимн".into(),
+                    start_ms: 3_725_500,
+                    end_ms: 3_726_000,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn ffmetadata_golden() {
+        assert_eq!(
+            chapters().ffmetadata(),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\ntitle=Звонок\\=1\\; \\#2\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=3725500\nEND=3726000\ntitle=Гимн\n"
+        );
+        assert_eq!(Metadata::default().ffmetadata(), ";FFMETADATA1\n");
+    }
+
+    #[test]
+    fn vorbis_chapters_golden() {
+        assert_eq!(
+            chapters().vorbis_comments(),
+            vec![
+                "TITLE=monday",
+                "CHAPTER001=00:00:00.000",
+                "CHAPTER001NAME=Звонок=1; #2",
+                "CHAPTER002=01:02:05.500",
+                "CHAPTER002NAME=Гимн",
+            ]
+        );
+    }
+}
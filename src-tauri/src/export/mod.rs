@@ -0,0 +1,633 @@
+pub mod cue;
+pub mod discovery;
+pub mod event_list;
+pub mod ffmpeg;
+pub mod flac;
+pub mod jobs;
+pub mod metadata;
+pub mod native;
+pub mod output;
+pub mod placements;
+pub mod playlist;
+pub mod preview;
+pub mod progress;
+pub mod split;
+pub mod verify;
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::audio::*;
+use metadata::{Chapter, Metadata};
+
+/// Сколько записей кодируется одновременно, если не указано явно.
+/// Каждая запись держит в памяти весь буфер (8 часов моно ≈ 5 ГБ).
+pub const DEFAULT_MAX_PARALLEL: usize = 2;
+
+pub type ProgressCallback = Arc<dyn Fn(ExportProgress) + Send + Sync>;
+
+/// Выбранный для экспорта кодировщик
+#[derive(Debug, Clone)]
+pub enum Encoder {
+    /// FFmpeg и имя его энкодера (`libmp3lame`, `libfdk_aac`, ...)
+    Ffmpeg {
+        path: PathBuf,
+        codec: String,
+    },
+    Native,
+}
+
+impl Encoder {
+    /// Выбирает кодировщик по настройкам экспорта.
+    ///
+    /// `find_ffmpeg` ищет FFmpeg только когда он нужен; в режиме `auto` его
+    /// отсутствие или нехватка энкодера не ошибка, если формат умеет
+    /// встроенный кодировщик.
+    pub fn select(
+        settings: &ExportSettings,
+        find_ffmpeg: impl FnOnce() -> Result<FfmpegInfo, String>,
+    ) -> Result<Self> {
+        validate_settings(settings)?;
+        let extension = settings.extension.as_str();
+        let with_ffmpeg = || -> Result<Self> {
+            let info = find_ffmpeg()
+                .map_err(|e| anyhow::anyhow!("Ошибка получения пути к FFmpeg: {e}"))?;
+            let codec = discovery::ensure_format(&info, settings)?;
+            Ok(Self::Ffmpeg {
+                path: PathBuf::from(info.path),
+                codec,
+            })
+        };
+
+        match settings.encoder {
+            EncoderBackend::Ffmpeg => with_ffmpeg(),
+            EncoderBackend::Native if native::supports(extension) => Ok(Self::Native),
+            EncoderBackend::Native => {
+                anyhow::bail!("Формат {extension} не поддерживается встроенным кодировщиком")
+            }
+            EncoderBackend::Auto => match with_ffmpeg() {
+                Ok(encoder) => Ok(encoder),
+                Err(e) if native::supports(extension) => {
+                    log::info!("{e:#}; используется встроенный кодировщик {extension}");
+                    Ok(Self::Native)
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &self,
+        samples: &[f32],
+        sample_rate: u32,
+        output_path: &Path,
+        settings: &ExportSettings,
+        metadata: &Metadata,
+        record_name: &str,
+        cancel: &CancelToken,
+        progress_callback: impl Fn(ExportProgress),
+    ) -> Result<()> {
+        match self {
+            Self::Ffmpeg { path, codec } => ffmpeg::export_samples_with_ffmpeg(
+                samples,
+                sample_rate,
+                output_path,
+                settings,
+                metadata,
+                path,
+                codec,
+                record_name,
+                cancel,
+                progress_callback,
+            ),
+            Self::Native => native::export_samples_native(
+                samples,
+                sample_rate,
+                output_path,
+                settings,
+                metadata,
+                record_name,
+                cancel,
+                progress_callback,
+            ),
+        }
+    }
+}
+
+/// Проверяет сочетания настроек, которые не зависят от кодировщика
+fn validate_settings(settings: &ExportSettings) -> Result<()> {
+    if settings.quality.is_some_and(|q| q > 10) {
+        anyhow::bail!("Качество VBR должно быть от 0 до 10");
+    }
+    if matches!(settings.extension.as_str(), "flac" | "alac")
+        && settings.bit_depth == BitDepth::Float32
+    {
+        anyhow::bail!(
+            "{} не поддерживает 32-bit float",
+            settings.extension.to_uppercase()
+        );
+    }
+    Ok(())
+}
+
+/// Записи, которые нужно экспортировать: `record_names` или все записи запроса
+pub fn requested_records(request: &ExportRequest) -> Vec<String> {
+    if request.record_names.is_empty() {
+        let mut names: Vec<String> = request.time_of_records.keys().cloned().collect();
+        names.sort();
+        names
+    } else {
+        request.record_names.clone()
+    }
+}
+
+/// Записанная запись: файл (или индекс частей) и проверки записанных файлов
+#[derive(Debug, Clone)]
+pub struct ExportedRecord {
+    pub path: PathBuf,
+    pub verification: Vec<Verification>,
+}
+
+/// Рендерит запись из уже декодированных источников и кодирует её в файл
+#[allow(clippy::too_many_arguments)]
+pub fn export_record(
+    processor: &AudioProcessor,
+    request: &ExportRequest,
+    record_name: &str,
+    audio_cache: &SourceCache,
+    output_dir: &Path,
+    encoder: &Encoder,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<ExportedRecord> {
+    let arrangements = schedule::resolve_record(request, record_name)?;
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Time record not found")?;
+
+    let mut metadata = Metadata::resolve(request, record_name)?;
+
+    // Имя единственного файла проверяется до рендеринга, чтобы `fail` не
+    // тратил время на запись, которую некуда сохранить
+    let single_path = (request.settings.split == SplitMode::None)
+        .then(|| {
+            resolve_output(
+                output_dir.join(format!(
+                    "{}.{}",
+                    record_name,
+                    request.settings.file_extension()
+                )),
+                &request.settings,
+            )
+        })
+        .transpose()?;
+
+    let rendered = processor.render_record(
+        record_name,
+        &arrangements,
+        time_record,
+        &request.sources,
+        audio_cache,
+        cancel,
+        &progress_callback,
+    )?;
+    let chapters = Chapter::from_record(&rendered);
+    if request.settings.chapters.enabled {
+        metadata.chapters = chapters.clone();
+    }
+
+    let parts = split::plan(&request.settings, &rendered)?;
+    let largest = parts
+        .iter()
+        .map(|part| part.range.len())
+        .max()
+        .unwrap_or(rendered.samples.len());
+    let warning = capacity_warning(&request.settings, &metadata, largest);
+    if let Some(warning) = &warning {
+        log::warn!("{record_name}: {warning}");
+        progress_callback(ExportProgress {
+            stage: ExportStage::Encoding,
+            progress: 80.0,
+            message: warning.clone(),
+            record_name: Some(record_name.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
+        });
+    }
+
+    let exported = if let Some((final_path, cue_path)) = single_path {
+        let file = OutputFile {
+            samples: &rendered.samples,
+            path: &final_path,
+            cue_path: cue_path.as_deref(),
+            metadata: &metadata,
+            chapters: &chapters,
+            sample_rate: rendered.sample_rate,
+        };
+        let mut staged = output::Staged::default();
+        let verification = file.stage(
+            &mut staged,
+            encoder,
+            request,
+            record_name,
+            cancel,
+            &progress_callback,
+        )?;
+        staged.commit()?;
+        ExportedRecord {
+            path: final_path,
+            verification: verification.into_iter().collect(),
+        }
+    } else {
+        export_parts(
+            request,
+            record_name,
+            &rendered,
+            &parts,
+            &metadata,
+            &chapters,
+            output_dir,
+            encoder,
+            cancel,
+            &progress_callback,
+        )?
+    };
+
+    let mut message = "Экспорт завершен".to_string();
+    if let Some(warning) = warning {
+        message.push_str(&format!(". {warning}"));
+    }
+    if !exported.verification.is_empty() {
+        let warnings: usize = exported.verification.iter().map(|v| v.warnings.len()).sum();
+        message.push_str(&match warnings {
+            0 => ". Проверка пройдена".to_string(),
+            n => format!(". Проверка: предупреждений {n}"),
+        });
+    }
+    progress_callback(ExportProgress {
+        stage: ExportStage::Completed,
+        progress: 100.0,
+        message,
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+
+    Ok(exported)
+}
+
+/// Путь файла записи и его `.cue` (если включён) с одним номером ` (n)`
+fn resolve_output(path: PathBuf, settings: &ExportSettings) -> Result<(PathBuf, Option<PathBuf>)> {
+    let mut paths = vec![path];
+    if settings.chapters.cue {
+        paths.push(paths[0].with_extension("cue"));
+    }
+    let resolved = output::resolve_group(&paths, settings.overwrite)?;
+    Ok((resolved[0].clone(), resolved.get(1).cloned()))
+}
+
+/// Один выходной файл: вся запись или её часть
+struct OutputFile<'a> {
+    samples: &'a [f32],
+    path: &'a Path,
+    /// Куда писать `.cue`; `None` — `.cue` выключен
+    cue_path: Option<&'a Path>,
+    metadata: &'a Metadata,
+    /// Главы для `.cue` и проверки (есть и при выключенных главах внутри файла)
+    chapters: &'a [Chapter],
+    sample_rate: u32,
+}
+
+impl OutputFile<'_> {
+    /// Кодирует файл во временный рядом с результатом, проверяет его (при
+    /// `verify`) и добавляет вместе с `.cue` в `staged`. Результат заменяется
+    /// только при фиксации `staged`; до неё прежний файл остаётся.
+    fn stage(
+        &self,
+        staged: &mut output::Staged,
+        encoder: &Encoder,
+        request: &ExportRequest,
+        record_name: &str,
+        cancel: &CancelToken,
+        progress_callback: impl Fn(ExportProgress),
+    ) -> Result<Option<Verification>> {
+        let temp = staged.temp(self.path);
+        encoder.encode(
+            self.samples,
+            self.sample_rate,
+            &temp,
+            &request.settings,
+            self.metadata,
+            record_name,
+            cancel,
+            &progress_callback,
+        )?;
+
+        let verification = if request.settings.verify {
+            progress_callback(ExportProgress {
+                stage: ExportStage::Encoding,
+                progress: 95.0,
+                message: format!("Проверка файла {}", self.path.display()),
+                record_name: Some(record_name.to_string()),
+                elapsed_ms: None,
+                eta_ms: None,
+                job_id: None,
+            });
+            let mut verification = verify::verify_output(
+                &temp,
+                &request.settings,
+                self.samples,
+                self.chapters,
+                self.sample_rate,
+            )
+            .with_context(|| format!("Проверка {} не пройдена", self.path.display()))?;
+            // Проверялся временный файл, в результате — имя итогового
+            verification.file = self
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            for warning in &verification.warnings {
+                log::warn!("{}: {warning}", verification.file);
+            }
+            Some(verification)
+        } else {
+            None
+        };
+
+        if let Some(cue_path) = self.cue_path.filter(|_| !self.chapters.is_empty()) {
+            let title = self
+                .metadata
+                .tags
+                .iter()
+                .find(|(key, _)| key == "title")
+                .map_or(record_name, |(_, value)| value.as_str());
+            staged.write(cue_path, cue::cue_sheet(self.path, title, self.chapters)?)?;
+        }
+        Ok(verification)
+    }
+}
+
+/// Кодирует части разбитой записи и пишет `{record}_index.json`.
+/// Части заменяют прежние файлы только все вместе, после кодирования
+/// последней; при ошибке существующие файлы не трогаются.
+#[allow(clippy::too_many_arguments)]
+fn export_parts(
+    request: &ExportRequest,
+    record_name: &str,
+    rendered: &RenderedRecord,
+    parts: &[split::Part],
+    metadata: &Metadata,
+    chapters: &[Chapter],
+    output_dir: &Path,
+    encoder: &Encoder,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<ExportedRecord> {
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Time record not found")?;
+    let zone = zone::ProjectZone::from_name(request.time_zone.as_deref())?;
+    let wall_clock = |ms: u64| {
+        zone.local_datetime(&(time_record.start + chrono::Duration::milliseconds(ms as i64)))
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string()
+    };
+
+    // Все имена выбираются до кодирования: `fail` не тратит время на части,
+    // которые некуда сохранить
+    let part_paths = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            resolve_output(
+                output_dir.join(split::part_file_name(
+                    record_name,
+                    i,
+                    part,
+                    request.settings.file_extension(),
+                )),
+                &request.settings,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let index_path = output::resolve(
+        &output_dir.join(format!("{record_name}_index.json")),
+        request.settings.overwrite,
+    )?;
+
+    let mut staged = output::Staged::default();
+    let mut verification = Vec::new();
+    let mut index = SplitIndex {
+        record_name: record_name.to_string(),
+        split: request.settings.split,
+        parts: Vec::with_capacity(parts.len()),
+    };
+
+    for (i, (part, (path, cue_path))) in parts.iter().zip(&part_paths).enumerate() {
+        cancel.check()?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let start_ms = rendered.ms(part.range.start);
+        let end_ms = rendered.ms(part.range.end);
+
+        let part_chapters = Chapter::within(chapters, start_ms, end_ms);
+        let mut part_metadata = metadata.clone();
+        part_metadata.chapters = Chapter::within(&metadata.chapters, start_ms, end_ms);
+        if let Some((_, title)) = part_metadata
+            .tags
+            .iter_mut()
+            .find(|(key, _)| key == "title")
+        {
+            *title = match &part.marker {
+                Some(marker) => marker.title.clone(),
+                None => format!("{title} ({}/{})", i + 1, parts.len()),
+            };
+        }
+
+        log::info!(
+            "{record_name}: часть {}/{} → {file_name}",
+            i + 1,
+            parts.len()
+        );
+        let file = OutputFile {
+            samples: &rendered.samples[part.range.clone()],
+            path,
+            cue_path: cue_path.as_deref(),
+            metadata: &part_metadata,
+            chapters: &part_chapters,
+            sample_rate: rendered.sample_rate,
+        };
+        verification.extend(file.stage(
+            &mut staged,
+            encoder,
+            request,
+            record_name,
+            cancel,
+            &progress_callback,
+        )?);
+
+        index.parts.push(SplitPart {
+            file: file_name,
+            start_ms,
+            end_ms,
+            start_time: wall_clock(start_ms),
+            end_time: wall_clock(end_ms),
+            arrangement_id: part.marker.as_ref().map(|m| m.arrangement_id.clone()),
+            title: part.marker.as_ref().map(|m| m.title.clone()),
+        });
+    }
+
+    staged.write(&index_path, serde_json::to_string_pretty(&index)?)?;
+    staged.commit()?;
+    Ok(ExportedRecord {
+        path: index_path,
+        verification,
+    })
+}
+
+/// Предупреждение, если запись не помещается в обычный контейнер формата
+fn capacity_warning(
+    settings: &ExportSettings,
+    metadata: &Metadata,
+    sample_count: usize,
+) -> Option<String> {
+    let bytes_per_sample = settings.bit_depth.integer_bits().unwrap_or(32) / 8;
+    let info_size = wav::info_size(&metadata.riff_info());
+    (settings.extension == "wav" && wav::needs_rf64(sample_count, bytes_per_sample, info_size))
+        .then(|| {
+            "WAV больше 4 ГБ сохранён в формате RF64, его открывают не все программы; \
+         для совместимости выберите FLAC"
+                .to_string()
+        })
+}
+
+/// Этап прогресса для ошибки экспорта: отмена или настоящая ошибка
+pub fn failure_progress(error: &anyhow::Error, record_name: Option<&str>) -> ExportProgress {
+    let cancelled = error.downcast_ref::<Cancelled>().is_some();
+    ExportProgress {
+        stage: if cancelled {
+            ExportStage::Cancelled
+        } else {
+            ExportStage::Error
+        },
+        progress: 100.0,
+        message: format!("{error:#}"),
+        record_name: record_name.map(str::to_string),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    }
+}
+
+/// Экспортирует несколько записей одного запроса.
+///
+/// Источники декодируются один раз для всех записей, записи рендерятся
+/// и кодируются параллельно, но не более `max_parallel` одновременно.
+/// Ошибка одной записи не прерывает экспорт остальных.
+pub async fn export_records(
+    request: ExportRequest,
+    record_names: Vec<String>,
+    output_dir: PathBuf,
+    encoder: Encoder,
+    max_parallel: usize,
+    cancel: CancelToken,
+    progress_callback: ProgressCallback,
+) -> Vec<RecordExportResult> {
+    let processor = Arc::new(AudioProcessor::new());
+    let audio_cache = {
+        let processor = processor.clone();
+        let sources = request.sources.clone();
+        let cancel = cancel.clone();
+        let progress_callback = progress_callback.clone();
+        tokio::task::spawn_blocking(move || {
+            processor.load_sources(&sources, None, &cancel, |p| progress_callback(p))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|cache| cache)
+    };
+    let audio_cache = match audio_cache {
+        Ok(cache) => Arc::new(cache),
+        Err(e) => {
+            progress_callback(failure_progress(&e, None));
+            return record_names
+                .into_iter()
+                .map(|record_name| RecordExportResult {
+                    record_name,
+                    output_path: None,
+                    error: Some(format!("{e:#}")),
+                    verification: Vec::new(),
+                })
+                .collect();
+        }
+    };
+
+    let request = Arc::new(request);
+    let semaphore = Arc::new(Semaphore::new(max_parallel.max(1)));
+    let mut handles = Vec::with_capacity(record_names.len());
+
+    for record_name in record_names {
+        let semaphore = semaphore.clone();
+        let processor = processor.clone();
+        let request = request.clone();
+        let audio_cache = audio_cache.clone();
+        let output_dir = output_dir.clone();
+        let encoder = encoder.clone();
+        let cancel = cancel.clone();
+        let progress_callback = progress_callback.clone();
+
+        handles.push((
+            record_name.clone(),
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                tokio::task::spawn_blocking(move || {
+                    export_record(
+                        &processor,
+                        &request,
+                        &record_name,
+                        &audio_cache,
+                        &output_dir,
+                        &encoder,
+                        &cancel,
+                        |p| progress_callback(p),
+                    )
+                    .inspect_err(|e| progress_callback(failure_progress(e, Some(&record_name))))
+                })
+                .await?
+            }),
+        ));
+    }
+
+    let mut results = Vec::with_capacity(handles.len());
+    for (record_name, handle) in handles {
+        let result = match handle.await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        results.push(match result {
+            Ok(exported) => RecordExportResult {
+                record_name,
+                output_path: Some(exported.path.to_string_lossy().to_string()),
+                error: None,
+                verification: exported.verification,
+            },
+            Err(e) => RecordExportResult {
+                record_name,
+                output_path: None,
+                error: Some(format!("{e:#}")),
+                verification: Vec::new(),
+            },
+        });
+    }
+    results
+}
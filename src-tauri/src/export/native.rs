@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use hound::{WavSpec, WavWriter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::audio::*;
+use crate::export::flac::FlacWriter;
+use crate::export::metadata::Metadata;
+
+/// Форматы, которые кодируются без FFmpeg
+pub const NATIVE_FORMATS: &[&str] = &["wav", "flac"];
+
+pub fn supports(extension: &str) -> bool {
+    NATIVE_FORMATS.contains(&extension)
+}
+
+/// Кодирует моно f32 сэмплы встроенным кодировщиком (WAV через hound, FLAC)
+#[allow(clippy::too_many_arguments)]
+pub fn export_samples_native(
+    samples: &[f32],
+    sample_rate: u32,
+    output_path: &Path,
+    settings: &ExportSettings,
+    metadata: &Metadata,
+    record_name: &str,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<()> {
+    log::info!(
+        "Экспорт {} сэмплов в {} встроенным кодировщиком",
+        samples.len(),
+        settings.extension
+    );
+
+    let bits = settings.bit_depth.integer_bits();
+    let info = metadata.riff_info();
+    let mut sink = match settings.extension.as_str() {
+        "wav" if wav::needs_rf64(samples.len(), bits.unwrap_or(32) / 8, wav::info_size(&info)) => {
+            log::warn!(
+                "WAV больше 4 ГБ, записываем RF64: {}",
+                output_path.display()
+            );
+            Sink::Rf64(Rf64Writer::create(
+                output_path,
+                sample_rate,
+                bits.unwrap_or(32),
+                bits.is_none(),
+            )?)
+        }
+        "wav" => {
+            let spec = WavSpec {
+                channels: 1,
+                sample_rate,
+                bits_per_sample: bits.unwrap_or(32) as u16,
+                sample_format: match bits {
+                    Some(_) => hound::SampleFormat::Int,
+                    None => hound::SampleFormat::Float,
+                },
+            };
+            let file = File::create(output_path).with_context(|| {
+                format!("Failed to create output file: {}", output_path.display())
+            })?;
+            Sink::Wav(
+                WavWriter::new(BufWriter::new(file), spec)
+                    .with_context(|| "Failed to create WAV writer")?,
+            )
+        }
+        "flac" => {
+            let bits = bits.context("FLAC не поддерживает 32-bit float")?;
+            Sink::Flac(FlacWriter::create(
+                output_path,
+                sample_rate,
+                bits,
+                &metadata.vorbis_comments(),
+            )?)
+        }
+        other => anyhow::bail!("Формат {} не поддерживается встроенным кодировщиком", other),
+    };
+
+    let chunk_size = sample_rate as usize * 4; // 4 секунды за раз
+    let total_chunks = samples.len().div_ceil(chunk_size).max(1);
+    let mut quantizer = bits.map(|bits| Quantizer::new(bits, settings.dither));
+    let mut pcm = Vec::with_capacity(chunk_size);
+
+    for (i, chunk) in samples.chunks(chunk_size).enumerate() {
+        cancel.check()?;
+
+        match quantizer.as_mut() {
+            Some(quantizer) => {
+                pcm.clear();
+                pcm.extend(chunk.iter().map(|&sample| quantizer.quantize(sample)));
+                sink.write(&pcm)?;
+            }
+            None => sink.write_float(chunk)?,
+        }
+
+        let prog = 80.0 + (i + 1) as f32 / total_chunks as f32 * 19.0;
+        progress_callback(ExportProgress {
+            stage: ExportStage::Encoding,
+            progress: prog,
+            message: format!("{}: {:.1}%", settings.extension, (prog - 80.0) / 0.19),
+            record_name: Some(record_name.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
+        });
+    }
+
+    sink.finalize()?;
+    if settings.extension == "wav" {
+        wav::append_info(output_path, &info)?;
+    }
+    Ok(())
+}
+
+enum Sink {
+    Wav(WavWriter<BufWriter<File>>),
+    Flac(FlacWriter),
+    Rf64(Rf64Writer),
+}
+
+impl Sink {
+    fn write(&mut self, pcm: &[i32]) -> Result<()> {
+        match self {
+            Sink::Wav(writer) => {
+                for &sample in pcm {
+                    writer
+                        .write_sample(sample)
+                        .context("Failed to write sample")?;
+                }
+                Ok(())
+            }
+            Sink::Flac(writer) => writer.write_samples(pcm),
+            Sink::Rf64(writer) => pcm.iter().try_for_each(|&sample| writer.write_int(sample)),
+        }
+    }
+
+    fn write_float(&mut self, samples: &[f32]) -> Result<()> {
+        match self {
+            Sink::Wav(writer) => {
+                for &sample in samples {
+                    writer
+                        .write_sample(sample.clamp(-1.0, 1.0))
+                        .context("Failed to write sample")?;
+                }
+                Ok(())
+            }
+            Sink::Flac(_) => anyhow::bail!("FLAC не поддерживает 32-bit float"),
+            Sink::Rf64(writer) => samples
+                .iter()
+                .try_for_each(|&sample| writer.write_float(sample.clamp(-1.0, 1.0))),
+        }
+    }
+
+    fn finalize(self) -> Result<()> {
+        match self {
+            Sink::Wav(writer) => writer.finalize().context("Failed to finalize WAV file"),
+            Sink::Flac(writer) => writer.finalize(),
+            Sink::Rf64(writer) => writer.finalize(),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::audio::OverwritePolicy;
+
+/// Сколько имён ` (n)` перебирается в режиме `KeepBoth`
+const MAX_KEEP_BOTH: u32 = 9999;
+
+/// Путь результата с учётом уже существующего файла
+pub fn resolve(path: &Path, policy: OverwritePolicy) -> Result<PathBuf> {
+    let mut resolved = resolve_group(&[path.to_path_buf()], policy)?;
+    Ok(resolved.remove(0))
+}
+
+/// Пути связанных файлов одного результата (файл и его `.cue`): в режиме
+/// `KeepBoth` все получают один номер ` (n)`, свободный для каждого
+pub fn resolve_group(paths: &[PathBuf], policy: OverwritePolicy) -> Result<Vec<PathBuf>> {
+    let Some(existing) = paths.iter().find(|path| path.exists()) else {
+        return Ok(paths.to_vec());
+    };
+    match policy {
+        OverwritePolicy::Overwrite => Ok(paths.to_vec()),
+        OverwritePolicy::Fail => bail!("Файл {} уже существует", existing.display()),
+        OverwritePolicy::KeepBoth => (1..=MAX_KEEP_BOTH)
+            .map(|n| {
+                paths
+                    .iter()
+                    .map(|path| numbered(path, n))
+                    .collect::<Vec<_>>()
+            })
+            .find(|candidates| candidates.iter().all(|candidate| !candidate.exists()))
+            .with_context(|| format!("Не найдено свободное имя для {}", existing.display())),
+    }
+}
+
+/// `stem (n).ext`
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{stem} ({n}){extension}"))
+}
+
+/// Временный файл рядом с результатом: в той же папке (переименование
+/// атомарно) и с тем же расширением (по нему FFmpeg выбирает контейнер)
+pub fn temp_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let mut name = format!(".{stem}.{}.tmp", &id[..8]);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Заменяет результат полностью записанным временным файлом
+pub fn commit(temp: &Path, path: &Path) -> Result<()> {
+    std::fs::rename(temp, path).with_context(|| {
+        format!(
+            "Не удалось переименовать {} в {}",
+            temp.display(),
+            path.display()
+        )
+    })
+}
+
+/// Записывает небольшой файл (индекс, манифест) через временный файл
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let temp = temp_path(path);
+    if let Err(e) = std::fs::write(&temp, contents) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Не удалось записать {}", path.display()));
+    }
+    commit(&temp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+/// Файлы результата, записанные во временные. Результат из нескольких
+/// файлов (части записи, фрагменты, `.cue`, индексы) заменяет прежние
+/// только целиком: временные файлы переименовываются после записи всех,
+/// а незафиксированные удаляются при сбросе — существующие файлы при
+/// ошибке или отмене остаются нетронутыми.
+#[derive(Default)]
+pub struct Staged {
+    /// Пары (временный файл, результат)
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Staged {
+    /// Временный файл, который станет результатом `path`
+    pub fn temp(&mut self, path: &Path) -> PathBuf {
+        let temp = temp_path(path);
+        self.files.push((temp.clone(), path.to_path_buf()));
+        temp
+    }
+
+    /// Записывает небольшой файл (индекс, манифест, плейлист)
+    pub fn write(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+        let temp = self.temp(path);
+        std::fs::write(&temp, contents)
+            .with_context(|| format!("Не удалось записать {}", path.display()))
+    }
+
+    /// Переименовывает все временные файлы в результаты.
+    ///
+    /// Прежние файлы сначала отодвигаются в резервные копии: если одно из
+    /// переименований не удалось, уже заменённые файлы возвращаются на место,
+    /// и в папке остаётся прежний результат целиком.
+    pub fn commit(mut self) -> Result<()> {
+        // (результат, резервная копия прежнего файла)
+        let mut replaced: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+        let result = (|| -> Result<()> {
+            for (temp, path) in &self.files {
+                let backup = if path.exists() {
+                    let backup = temp_path(path);
+                    commit(path, &backup)?;
+                    Some(backup)
+                } else {
+                    None
+                };
+                replaced.push((path, backup));
+                commit(temp, path)?;
+            }
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => {
+                for backup in replaced.iter().filter_map(|(_, backup)| backup.as_ref()) {
+                    if let Err(e) = std::fs::remove_file(backup) {
+                        log::warn!("Не удалось удалить {}: {}", backup.display(), e);
+                    }
+                }
+            }
+            Err(_) => {
+                for (path, backup) in replaced.iter().rev() {
+                    let restored = match backup {
+                        Some(backup) => std::fs::rename(backup, path),
+                        None if path.exists() => std::fs::remove_file(path),
+                        None => Ok(()),
+                    };
+                    if let Err(e) = restored {
+                        log::error!("Не удалось восстановить {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+        // Временные файлы уже переименованы; при ошибке оставшиеся удалит `Drop`
+        drop(replaced);
+        if result.is_ok() {
+            self.files.clear();
+        }
+        result
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        for (temp, _) in &self.files {
+            if temp.exists() {
+                match std::fs::remove_file(temp) {
+                    Ok(()) => log::info!("Удален недописанный файл {}", temp.display()),
+                    Err(e) => log::warn!("Не удалось удалить {}: {}", temp.display(), e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Пустая папка для теста; удаляется вместе с содержимым
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("output-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn file(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn files(&self) -> Vec<String> {
+            let mut names: Vec<_> = std::fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        }
+
+        fn read(&self, name: &str) -> String {
+            std::fs::read_to_string(self.0.join(name)).unwrap()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn resolve_policies() {
+        let dir = TestDir::new();
+        let free = dir.0.join("new.wav");
+        let taken = dir.file("rec.wav", "old");
+        dir.file("rec (1).wav", "old");
+
+        for policy in [
+            OverwritePolicy::Overwrite,
+            OverwritePolicy::Fail,
+            OverwritePolicy::KeepBoth,
+        ] {
+            assert_eq!(resolve(&free, policy).unwrap(), free);
+        }
+        assert_eq!(resolve(&taken, OverwritePolicy::Overwrite).unwrap(), taken);
+        let error = resolve(&taken, OverwritePolicy::Fail).unwrap_err();
+        assert!(error.to_string().contains("уже существует"));
+        assert_eq!(
+            resolve(&taken, OverwritePolicy::KeepBoth).unwrap(),
+            dir.0.join("rec (2).wav")
+        );
+    }
+
+    #[test]
+    fn keep_both_numbers_the_group_together() {
+        let dir = TestDir::new();
+        dir.file("rec.wav", "old");
+        dir.file("rec (1).cue", "old");
+        let group = [dir.0.join("rec.wav"), dir.0.join("rec.cue")];
+
+        let resolved = resolve_group(&group, OverwritePolicy::KeepBoth).unwrap();
+        assert_eq!(
+            resolved,
+            [dir.0.join("rec (2).wav"), dir.0.join("rec (2).cue")]
+        );
+        // Занят только `.cue` — группа всё равно переименовывается целиком
+        std::fs::remove_file(dir.0.join("rec.wav")).unwrap();
+        dir.file("rec.cue", "old");
+        assert_eq!(
+            resolve_group(&group, OverwritePolicy::KeepBoth).unwrap()[0],
+            dir.0.join("rec (2).wav")
+        );
+        assert!(resolve_group(&group, OverwritePolicy::Fail).is_err());
+    }
+
+    #[test]
+    fn dropped_set_removes_temps_only() {
+        let dir = TestDir::new();
+        let existing = dir.file("rec.wav", "old");
+        {
+            let mut staged = Staged::default();
+            staged.write(&existing, "new").unwrap();
+            staged.write(&dir.0.join("rec.cue"), "new").unwrap();
+        }
+        assert_eq!(dir.files(), ["rec.wav"]);
+        assert_eq!(dir.read("rec.wav"), "old");
+    }
+
+    #[test]
+    fn commit_replaces_every_file() {
+        let dir = TestDir::new();
+        let existing = dir.file("rec.wav", "old");
+        let mut staged = Staged::default();
+        staged.write(&existing, "new").unwrap();
+        staged.write(&dir.0.join("rec.cue"), "new").unwrap();
+        staged.commit().unwrap();
+
+        assert_eq!(dir.files(), ["rec.cue", "rec.wav"]);
+        assert_eq!(dir.read("rec.wav"), "new");
+        assert_eq!(dir.read("rec.cue"), "new");
+    }
+
+    #[test]
+    fn failed_commit_restores_previous_files() {
+        let dir = TestDir::new();
+        let first = dir.file("part_1.wav", "old");
+        let second = dir.file("part_2.wav", "old");
+        let mut staged = Staged::default();
+        staged.write(&first, "new").unwrap();
+        staged.write(&dir.0.join("part_1.cue"), "new").unwrap();
+        // Переименование второй части не удастся: временного файла нет
+        let temp = staged.temp(&second);
+        assert!(!temp.exists());
+
+        assert!(staged.commit().is_err());
+        assert_eq!(dir.files(), ["part_1.wav", "part_2.wav"]);
+        assert_eq!(dir.read("part_1.wav"), "old");
+        assert_eq!(dir.read("part_2.wav"), "old");
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use chrono::Duration;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use super::output;
+use crate::audio::processor::FADE_SECONDS;
+use crate::audio::timeline::Marker;
+use crate::audio::zone::ProjectZone;
+use crate::audio::*;
+
+/// Кадров в секунде таймкода EDL (PAL)
+const EDL_FPS: u64 = 25;
+
+/// Экспортирует размещения объявлений записи в выбранных форматах.
+///
+/// Позиции считаются тем же размещением, что и при рендеринге, поэтому
+/// источники декодируются (их длина влияет на обрезку), но звук записи
+/// не рендерится.
+pub fn export_timeline(
+    request: &ExportRequest,
+    record_name: &str,
+    formats: &[TimelineFormat],
+    output_dir: &Path,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<Vec<PathBuf>> {
+    let arrangements = schedule::resolve_record(request, record_name)?;
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Time record not found")?;
+    let zone = ProjectZone::from_name(request.time_zone.as_deref())?;
+    // Имена проверяются до декодирования источников
+    let paths = formats
+        .iter()
+        .map(|format| {
+            let file_name = match format {
+                TimelineFormat::Audacity => format!("{record_name}_labels.txt"),
+                TimelineFormat::Csv => format!("{record_name}.csv"),
+                TimelineFormat::Edl => format!("{record_name}.edl"),
+            };
+            output::resolve(&output_dir.join(file_name), request.settings.overwrite)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let processor = AudioProcessor::new();
+    let audio_cache = processor.load_sources(
+        &request.sources,
+        Some(record_name),
+        cancel,
+        &progress_callback,
+    )?;
+    let markers =
+        processor.layout_record(&arrangements, time_record, &request.sources, &audio_cache)?;
+    cancel.check()?;
+
+    let sample_rate = processor.sample_rate();
+    let seconds = |position: usize| position as f64 / sample_rate as f64;
+    let wall_clock = |position: usize| {
+        let ms = position as i64 * 1000 / sample_rate as i64;
+        zone.local_datetime(&(time_record.start + Duration::milliseconds(ms)))
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string()
+    };
+
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Не удалось создать {}", output_dir.display()))?;
+
+    let mut staged = output::Staged::default();
+    for (format, path) in formats.iter().zip(&paths) {
+        let content = match format {
+            TimelineFormat::Audacity => audacity_labels(&markers, seconds),
+            TimelineFormat::Csv => csv(&markers, seconds, wall_clock),
+            TimelineFormat::Edl => edl(record_name, &markers, sample_rate),
+        };
+        staged.write(path, content)?;
+    }
+    staged.commit()?;
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Completed,
+        progress: 100.0,
+        message: format!("Размещения записи {record_name}: {} шт.", markers.len()),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+
+    Ok(paths)
+}
+
+fn audacity_labels(markers: &[Marker], seconds: impl Fn(usize) -> f64) -> String {
+    let mut labels = String::new();
+    for marker in markers {
+        // Табуляция и перевод строки разделяют поля и метки
+        let title = marker.title.replace(['\t', '\n', '\r'], " ");
+        let _ = writeln!(
+            labels,
+            "{:.6}\t{:.6}\t{title}",
+            seconds(marker.range.start),
+            seconds(marker.range.end)
+        );
+    }
+    labels
+}
+
+fn csv(
+    markers: &[Marker],
+    seconds: impl Fn(usize) -> f64,
+    wall_clock: impl Fn(usize) -> String,
+) -> String {
+    let mut csv = String::from(
+        "arrangement_id,type_id,source,start_sample,end_sample,start_seconds,end_seconds,\
+         start_time,end_time,gain,fade_in,fade_out\n",
+    );
+    for marker in markers {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{:.3},{:.3},{},{},{:.2},{},{}",
+            csv_field(&marker.arrangement_id),
+            csv_field(marker.type_id.as_deref().unwrap_or("")),
+            csv_field(&marker.title),
+            marker.range.start,
+            marker.range.end,
+            seconds(marker.range.start),
+            seconds(marker.range.end),
+            wall_clock(marker.range.start),
+            wall_clock(marker.range.end),
+            marker.gain,
+            marker.fade_in,
+            marker.fade_out
+        );
+    }
+    csv
+}
+
+/// Поле CSV: в кавычках, если содержит разделители или кавычки
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// EDL в стиле CMX3600: одно событие на объявление, моно дорожка A.
+///
+/// Таймкод записи отсчитывается от 00:00:00:00, таймкод источника — от
+/// начала его файла; громкость и фейды передаются комментариями.
+fn edl(record_name: &str, markers: &[Marker], sample_rate: u32) -> String {
+    let timecode = |position: usize| {
+        let frames = position as u64 * EDL_FPS / sample_rate as u64;
+        let seconds = frames / EDL_FPS;
+        format!(
+            "{:02}:{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            frames % EDL_FPS
+        )
+    };
+
+    let fade_ms = (FADE_SECONDS * 1000.0) as u32;
+    let mut edl = format!("TITLE: {record_name}\nFCM: NON-DROP FRAME\n\n");
+    for (i, marker) in markers.iter().enumerate() {
+        let length = marker.range.len();
+        let _ = writeln!(
+            edl,
+            "{:03}  {:<8} {:<5} {:<4}     {} {} {} {}",
+            i + 1,
+            "AX",
+            "A",
+            "C",
+            timecode(marker.source_offset),
+            timecode(marker.source_offset + length),
+            timecode(marker.range.start),
+            timecode(marker.range.end)
+        );
+        let _ = writeln!(edl, "* FROM CLIP NAME: {}", marker.title);
+        if marker.gain != 1.0 {
+            let _ = writeln!(
+                edl,
+                "* AUDIO LEVEL AT {} IS {:+.2} DB  (REEL AX A1)",
+                timecode(marker.range.start),
+                20.0 * marker.gain.max(f32::MIN_POSITIVE).log10()
+            );
+        }
+        if marker.fade_in {
+            let _ = writeln!(edl, "* FADE IN {fade_ms} MS");
+        }
+        if marker.fade_out {
+            let _ = writeln!(edl, "* FADE OUT {fade_ms} MS");
+        }
+        edl.push('\n');
+    }
+    edl
+}
@@ -0,0 +1,458 @@
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use super::metadata::clock;
+use super::output;
+use crate::audio::timeline::Timeline;
+use crate::audio::zone::ProjectZone;
+use crate::audio::*;
+
+/// Плейлист записи: элементы в порядке звучания
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    pub title: String,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+/// Элемент плейлиста: файл (или его отрезок), звучащий с заданного момента
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    /// Путь к файлу как есть: абсолютный для источников, относительный для фрагментов
+    pub location: String,
+    pub title: String,
+    /// Начало звучания от начала записи
+    pub offset_ms: u64,
+    /// Местное время начала
+    pub start_time: String,
+    pub duration_ms: u64,
+    /// Начало отрезка внутри файла; `None` — файл играет с начала
+    pub file_start_ms: Option<u64>,
+}
+
+impl PlaylistEntry {
+    fn end_ms(&self) -> u64 {
+        self.offset_ms + self.duration_ms
+    }
+}
+
+impl Playlist {
+    /// Плейлист из фрагментов компактного экспорта; пути относительно манифеста
+    pub fn from_event_list(list: &EventList) -> Self {
+        let entries = list
+            .events
+            .iter()
+            .map(|event| {
+                let clip = &list.clips[event.clip];
+                PlaylistEntry {
+                    location: clip.file.clone(),
+                    title: clip.title.clone(),
+                    offset_ms: event.offset_ms,
+                    start_time: event.start_time.clone(),
+                    duration_ms: event.duration_ms,
+                    file_start_ms: None,
+                }
+            })
+            .collect();
+        Self {
+            title: list.record_name.clone(),
+            entries,
+        }
+    }
+
+    /// Плейлист из исходных файлов: каждый элемент — обрезка источника с
+    /// нужного места. Зацикленное объявление даёт по элементу на каждый
+    /// проход. Громкость и фейды плейлисты не передают.
+    pub fn from_sources(
+        request: &ExportRequest,
+        record_name: &str,
+        cancel: &CancelToken,
+        progress_callback: impl Fn(ExportProgress),
+    ) -> Result<Self> {
+        let arrangements = schedule::resolve_record(request, record_name)?;
+        let time_record = request
+            .time_of_records
+            .get(record_name)
+            .context("Time record not found")?;
+        let zone = ProjectZone::from_name(request.time_zone.as_deref())?;
+
+        let processor = AudioProcessor::new();
+        let audio_cache = processor.load_sources(
+            &request.sources,
+            Some(record_name),
+            cancel,
+            &progress_callback,
+        )?;
+        let markers =
+            processor.layout_record(&arrangements, time_record, &request.sources, &audio_cache)?;
+        cancel.check()?;
+
+        let sample_rate = processor.sample_rate();
+        let timeline = Timeline::new(time_record, sample_rate);
+        let ms = |position: usize| position as u64 * 1000 / sample_rate as u64;
+        let wall_clock = |position: usize| {
+            let offset = chrono::Duration::milliseconds(ms(position) as i64);
+            zone.local_datetime(&(time_record.start + offset))
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string()
+        };
+
+        let mut entries = Vec::new();
+        for marker in &markers {
+            let Some(source) = request.sources.iter().find(|s| s.id == marker.source_id) else {
+                continue;
+            };
+            let Some(cut) = audio_cache
+                .get(&source.id)
+                .and_then(|samples| timeline.cut_range(&source.cut, samples.len()))
+            else {
+                continue;
+            };
+
+            let mut position = marker.range.start;
+            let mut file_position = marker.source_offset;
+            while position < marker.range.end {
+                let length = (marker.range.end - position).min(cut.end - file_position);
+                entries.push(PlaylistEntry {
+                    location: source.file_path.clone(),
+                    title: marker.title.clone(),
+                    offset_ms: ms(position),
+                    start_time: wall_clock(position),
+                    duration_ms: ms(length),
+                    file_start_ms: Some(ms(file_position)),
+                });
+                position += length;
+                file_position = cut.start;
+            }
+        }
+
+        Ok(Self {
+            title: record_name.to_string(),
+            entries,
+        })
+    }
+
+    /// Пауза перед элементом; перекрывающиеся элементы плееры сыграют подряд
+    fn gap_ms(&self, index: usize) -> u64 {
+        let previous_end = match index {
+            0 => 0,
+            i => self.entries[i - 1].end_ms(),
+        };
+        self.entries[index].offset_ms.saturating_sub(previous_end)
+    }
+
+    /// Расширенный M3U: длительность, смещение и пауза — атрибутами
+    /// `#EXTINF`, отрезок файла — опциями VLC `start-time`/`stop-time`
+    pub fn m3u8(&self) -> String {
+        let mut text = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(&self.title));
+        for (i, entry) in self.entries.iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "#EXTINF:{:.3} offset=\"{}\" time=\"{}\" gap=\"{:.3}\",{}",
+                seconds(entry.duration_ms),
+                clock(entry.offset_ms),
+                entry.start_time,
+                seconds(self.gap_ms(i)),
+                single_line(&entry.title)
+            );
+            if let Some(start) = entry.file_start_ms {
+                let _ = writeln!(
+                    text,
+                    "#EXTVLCOPT:start-time={:.3}\n#EXTVLCOPT:stop-time={:.3}",
+                    seconds(start),
+                    seconds(start + entry.duration_ms)
+                );
+            }
+            let _ = writeln!(text, "{}", entry.location);
+        }
+        text
+    }
+
+    /// PLS: смещения и паузы формат не поддерживает, длительность — в
+    /// целых секундах
+    pub fn pls(&self) -> String {
+        let mut text = String::from("[playlist]\n");
+        for (i, entry) in self.entries.iter().enumerate() {
+            let n = i + 1;
+            let _ = writeln!(text, "File{n}={}", entry.location);
+            let _ = writeln!(text, "Title{n}={}", single_line(&entry.title));
+            let _ = writeln!(text, "Length{n}={}", entry.duration_ms.div_ceil(1000));
+        }
+        let _ = writeln!(text, "NumberOfEntries={}\nVersion=2", self.entries.len());
+        text
+    }
+
+    /// XSPF: длительность в `<duration>`, время и пауза в `<annotation>`,
+    /// отрезок файла — расширением VLC
+    pub fn xspf(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\" \
+             xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\">\n",
+        );
+        let _ = writeln!(
+            xml,
+            "  <title>{}</title>\n  <trackList>",
+            escape_xml(&self.title)
+        );
+        for (i, entry) in self.entries.iter().enumerate() {
+            let _ = writeln!(xml, "    <track>");
+            let _ = writeln!(
+                xml,
+                "      <location>{}</location>",
+                escape_xml(&file_uri(&entry.location))
+            );
+            let _ = writeln!(xml, "      <title>{}</title>", escape_xml(&entry.title));
+            let _ = writeln!(
+                xml,
+                "      <annotation>{} (+{}, пауза {:.3} с)</annotation>",
+                entry.start_time,
+                clock(entry.offset_ms),
+                seconds(self.gap_ms(i))
+            );
+            let _ = writeln!(xml, "      <duration>{}</duration>", entry.duration_ms);
+            if let Some(start) = entry.file_start_ms {
+                let _ = writeln!(
+                    xml,
+                    "      <extension application=\"http://www.videolan.org/vlc/playlist/0\">\n\
+                     \x20       <vlc:option>start-time={:.3}</vlc:option>\n\
+                     \x20       <vlc:option>stop-time={:.3}</vlc:option>\n\
+                     \x20     </extension>",
+                    seconds(start),
+                    seconds(start + entry.duration_ms)
+                );
+            }
+            let _ = writeln!(xml, "    </track>");
+        }
+        xml.push_str("  </trackList>\n</playlist>\n");
+        xml
+    }
+}
+
+/// Пути плейлистов записи в выбранных форматах — `{record}.m3u8`, `.pls`,
+/// `.xspf` — с учётом уже существующих файлов
+pub fn playlist_paths(
+    record_name: &str,
+    formats: &[PlaylistFormat],
+    output_dir: &Path,
+    policy: OverwritePolicy,
+) -> Result<Vec<(PlaylistFormat, PathBuf)>> {
+    formats
+        .iter()
+        .map(|&format| {
+            let extension = match format {
+                PlaylistFormat::M3u8 => "m3u8",
+                PlaylistFormat::Pls => "pls",
+                PlaylistFormat::Xspf => "xspf",
+            };
+            let path = output::resolve(
+                &output_dir.join(format!("{record_name}.{extension}")),
+                policy,
+            )?;
+            Ok((format, path))
+        })
+        .collect()
+}
+
+/// Записывает плейлист по путям из [`playlist_paths`]
+pub fn write_playlists(
+    playlist: &Playlist,
+    paths: &[(PlaylistFormat, PathBuf)],
+    staged: &mut output::Staged,
+) -> Result<()> {
+    for (format, path) in paths {
+        let content = match format {
+            PlaylistFormat::M3u8 => playlist.m3u8(),
+            PlaylistFormat::Pls => playlist.pls(),
+            PlaylistFormat::Xspf => playlist.xspf(),
+        };
+        staged.write(path, content)?;
+    }
+    Ok(())
+}
+
+/// Плейлисты записи со ссылками на исходные файлы, без рендеринга и кодирования
+pub fn export_playlists(
+    request: &ExportRequest,
+    record_name: &str,
+    formats: &[PlaylistFormat],
+    output_dir: &Path,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<Vec<PathBuf>> {
+    let paths = playlist_paths(record_name, formats, output_dir, request.settings.overwrite)?;
+    let playlist = Playlist::from_sources(request, record_name, cancel, &progress_callback)?;
+
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Не удалось создать {}", output_dir.display()))?;
+    let mut staged = output::Staged::default();
+    write_playlists(&playlist, &paths, &mut staged)?;
+    staged.commit()?;
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Completed,
+        progress: 100.0,
+        message: format!(
+            "Плейлист записи {record_name}: {} элементов",
+            playlist.entries.len()
+        ),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+    Ok(paths.into_iter().map(|(_, path)| path).collect())
+}
+
+fn seconds(ms: u64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+/// Строковые поля M3U и PLS не могут содержать перевод строки
+fn single_line(value: &str) -> String {
+    value.replace(['\n', '\r'], " ")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// URI для `<location>` XSPF: абсолютный путь — `file:///`, относительный
+/// остаётся относительной ссылкой; небезопасные символы кодируются
+fn file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let encode = |part: &str| -> String {
+        part.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                    (b as char).to_string()
+                }
+                b => format!("%{b:02X}"),
+            })
+            .collect()
+    };
+
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        // Путь Windows с буквой диска
+        format!("file:///{}{}", &path[..2], encode(&path[2..]))
+    } else if path.starts_with('/') {
+        format!("file://{}", encode(&path))
+    } else {
+        encode(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> Playlist {
+        Playlist {
+            title: "monday".into(),
+            entries: vec![
+                PlaylistEntry {
+                    location: "/music/bell 1.mp3".into(),
+                    title: "Звонок & <гимн>".into(),
+                    offset_ms: 1000,
+                    start_time: "2024-09-02 08:00:01.000".into(),
+                    duration_ms: 2500,
+                    file_start_ms: Some(500),
+                },
+                PlaylistEntry {
+                    location: "clips/002_b.mp3".into(),
+                    title: "Гимн \"утро\"".into(),
+                    offset_ms: 5000,
+                    start_time: "2024-09-02 08:00:05.000".into(),
+                    duration_ms: 1200,
+                    file_start_ms: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn m3u8_golden() {
+        assert_eq!(
+            playlist().m3u8(),
+            "#EXTM3U\n\
+             #PLAYLIST:monday\n\
+             #EXTINF:2.500 offset=\"00:00:01.000\" time=\"2024-09-02 08:00:01.000\" gap=\"1.000\",Звонок & <гимн>\n\
+             #EXTVLCOPT:start-time=0.500\n\
+             #EXTVLCOPT:stop-time=3.000\n\
+             /music/bell 1.mp3\n\
+             #EXTINF:1.200 offset=\"00:00:05.000\" time=\"2024-09-02 08:00:05.000\" gap=\"1.500\",Гимн \"утро\"\n\
+             clips/002_b.mp3\n"
+        );
+    }
+
+    #[test]
+    fn pls_golden() {
+        assert_eq!(
+            playlist().pls(),
+            "[playlist]\n\
+             File1=/music/bell 1.mp3\n\
+             Title1=Звонок & <гимн>\n\
+             Length1=3\n\
+             File2=clips/002_b.mp3\n\
+             Title2=Гимн \"утро\"\n\
+             Length2=2\n\
+             NumberOfEntries=2\n\
+             Version=2\n"
+        );
+    }
+
+    #[test]
+    fn xspf_golden() {
+        assert_eq!(
+            playlist().xspf(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/" xmlns:vlc="http://www.videolan.org/vlc/playlist/ns/0/">
+  <title>monday</title>
+  <trackList>
+    <track>
+      <location>file:///music/bell%201.mp3</location>
+      <title>Звонок &amp; &lt;гимн&gt;</title>
+      <annotation>2024-09-02 08:00:01.000 (+00:00:01.000, пауза 1.000 с)</annotation>
+      <duration>2500</duration>
+      <extension application="http://www.videolan.org/vlc/playlist/0">
+        <vlc:option>start-time=0.500</vlc:option>
+        <vlc:option>stop-time=3.000</vlc:option>
+      </extension>
+    </track>
+    <track>
+      <location>clips/002_b.mp3</location>
+      <title>Гимн &quot;утро&quot;</title>
+      <annotation>2024-09-02 08:00:05.000 (+00:00:05.000, пауза 1.500 с)</annotation>
+      <duration>1200</duration>
+    </track>
+  </trackList>
+</playlist>
+"#
+        );
+    }
+
+    #[test]
+    fn text_escaping() {
+        assert_eq!(
+            escape_xml(r#"a&b<c>"d" &lt;"#),
+            "a&amp;b&lt;c&gt;&quot;d&quot; &amp;lt;"
+        );
+        assert_eq!(single_line("a\r\nb\nc"), "a  b c");
+    }
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(
+            file_uri(r"C:\Музыка\a b.mp3"),
+            "file:///C:/%D0%9C%D1%83%D0%B7%D1%8B%D0%BA%D0%B0/a%20b.mp3"
+        );
+        assert_eq!(file_uri("d:/x.mp3"), "file:///d:/x.mp3");
+        assert_eq!(file_uri("/tmp/100%.mp3"), "file:///tmp/100%25.mp3");
+        assert_eq!(file_uri("clips/a&b#1.mp3"), "clips/a%26b%231.mp3");
+    }
+}
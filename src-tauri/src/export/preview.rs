@@ -0,0 +1,192 @@
+use anyhow::{bail, Context, Result};
+use chrono::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::audio::*;
+
+/// Рендерит фрагмент записи во временный WAV для прослушивания в интерфейсе.
+///
+/// Объявления разрешаются по всей записи, а в буфер попадает только окно,
+/// поэтому события на его границах звучат так же, как в полном экспорте.
+/// Нормализация считается по окну: если самый громкий момент записи
+/// лежит вне окна, громкость фрагмента может немного отличаться.
+pub fn render_preview(
+    request: &ExportRequest,
+    record_name: &str,
+    window: &PreviewWindow,
+    output_dir: &Path,
+    cancel: &CancelToken,
+    progress_callback: impl Fn(ExportProgress),
+) -> Result<PathBuf> {
+    let time_record = request
+        .time_of_records
+        .get(record_name)
+        .context("Time record not found")?;
+    let arrangements = schedule::resolve_record(request, record_name)?;
+
+    let (start, end) = match window {
+        PreviewWindow::Range { start, end } => (*start, *end),
+        PreviewWindow::Around {
+            arrangement_id,
+            padding_ms,
+        } => {
+            let arrangement = arrangements
+                .iter()
+                .find(|a| &a.id == arrangement_id)
+                .with_context(|| {
+                    format!("Объявление {arrangement_id} не найдено в записи {record_name}")
+                })?;
+            let padding = Duration::milliseconds((*padding_ms).max(0));
+            (
+                arrangement.playing_time.start - padding,
+                arrangement.playing_time.end + padding,
+            )
+        }
+    };
+
+    // Окно не выходит за пределы записи
+    let window_record = TimeOfRecord {
+        start: start.max(time_record.start),
+        end: end.min(time_record.end),
+    };
+    if window_record.start >= window_record.end {
+        bail!("Окно предпрослушивания не пересекается с записью {record_name}");
+    }
+
+    let processor = AudioProcessor::new();
+    let audio_cache = processor.load_sources(
+        &request.sources,
+        Some(record_name),
+        cancel,
+        &progress_callback,
+    )?;
+    let rendered = processor.render_record(
+        record_name,
+        &arrangements,
+        &window_record,
+        &request.sources,
+        &audio_cache,
+        cancel,
+        &progress_callback,
+    )?;
+
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Не удалось создать {}", output_dir.display()))?;
+    let output_path = output_dir.join(format!(
+        "{}{}-{}.wav",
+        PREVIEW_PREFIX,
+        record_name,
+        uuid::Uuid::new_v4()
+    ));
+    processor.save_as_wav(&rendered.samples, &output_path.to_string_lossy())?;
+
+    progress_callback(ExportProgress {
+        stage: ExportStage::Completed,
+        progress: 100.0,
+        message: "Предпрослушивание готово".to_string(),
+        record_name: Some(record_name.to_string()),
+        elapsed_ms: None,
+        eta_ms: None,
+        job_id: None,
+    });
+
+    Ok(output_path)
+}
+
+/// Префикс имён временных файлов предпрослушивания
+const PREVIEW_PREFIX: &str = "preview-";
+
+/// Временный файл последнего предпрослушивания.
+///
+/// Интерфейс проигрывает только последний фрагмент, поэтому новый рендер
+/// удаляет предыдущий файл, а при выходе удаляется и последний.
+#[derive(Default)]
+pub struct PreviewFiles {
+    current: Mutex<Option<PathBuf>>,
+}
+
+impl PreviewFiles {
+    /// Запоминает новый фрагмент и удаляет предыдущий
+    pub fn replace(&self, path: PathBuf) {
+        let previous = self.current.lock().unwrap().replace(path.clone());
+        if let Some(previous) = previous.filter(|previous| *previous != path) {
+            remove_preview(&previous);
+        }
+    }
+
+    /// Удаляет последний фрагмент
+    pub fn clear(&self) {
+        if let Some(path) = self.current.lock().unwrap().take() {
+            remove_preview(&path);
+        }
+    }
+}
+
+/// Удаляет фрагменты, оставшиеся от прошлых запусков (например, после падения)
+pub fn remove_stale(output_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(output_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(PREVIEW_PREFIX) && name.ends_with(".wav") {
+            remove_preview(&entry.path());
+        }
+    }
+}
+
+fn remove_preview(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Не удалось удалить {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("preview-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn new_preview_removes_previous() {
+        let dir = test_dir("replace");
+        let first = dir.join("preview-a-1.wav");
+        let second = dir.join("preview-a-2.wav");
+        std::fs::write(&first, b"1").unwrap();
+        std::fs::write(&second, b"2").unwrap();
+
+        let previews = PreviewFiles::default();
+        previews.replace(first.clone());
+        previews.replace(second.clone());
+        assert!(!first.exists());
+        assert!(second.exists());
+
+        // Выход из приложения удаляет последний фрагмент
+        previews.clear();
+        assert!(!second.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stale_previews_are_removed() {
+        let dir = test_dir("stale");
+        std::fs::write(dir.join("preview-a-1.wav"), b"1").unwrap();
+        // Другие временные файлы приложения не трогаем
+        std::fs::write(dir.join("upload.wav"), b"2").unwrap();
+
+        remove_stale(&dir);
+        assert!(!dir.join("preview-a-1.wav").exists());
+        assert!(dir.join("upload.wav").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
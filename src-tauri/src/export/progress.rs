@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::audio::*;
+use crate::export::ProgressCallback;
+
+/// Минимальный интервал между событиями прогресса одной записи
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+struct RecordClock {
+    started: Instant,
+    last_emit: Option<Instant>,
+    last_stage: Option<ExportStage>,
+}
+
+/// Оборачивает обработчик прогресса: заполняет `elapsed_ms`/`eta_ms`
+/// и пропускает не чаще одного события в `PROGRESS_INTERVAL` на запись.
+///
+/// Смена этапа и завершающие этапы отправляются всегда, поэтому интерфейс
+/// не пропускает `completed` или ошибку.
+pub fn throttled(callback: ProgressCallback) -> ProgressCallback {
+    let clocks: Mutex<HashMap<Option<String>, RecordClock>> = Mutex::new(HashMap::new());
+
+    Arc::new(move |mut progress: ExportProgress| {
+        let now = Instant::now();
+        let due = {
+            let mut clocks = clocks.lock().unwrap();
+            let clock = clocks
+                .entry(progress.record_name.clone())
+                .or_insert_with(|| RecordClock {
+                    started: now,
+                    last_emit: None,
+                    last_stage: None,
+                });
+
+            let elapsed = now - clock.started;
+            progress.elapsed_ms = Some(elapsed.as_millis() as u64);
+            progress.eta_ms = estimate_eta(elapsed, progress.progress);
+
+            let due = match clock.last_emit {
+                _ if progress.stage.is_final() => true,
+                _ if clock.last_stage != Some(progress.stage) => true,
+                Some(last) => now - last >= PROGRESS_INTERVAL,
+                None => true,
+            };
+            if due {
+                clock.last_emit = Some(now);
+                clock.last_stage = Some(progress.stage);
+            }
+            due
+        };
+
+        if due {
+            callback(progress);
+        }
+    })
+}
+
+/// Линейная оценка оставшегося времени; до первого процента не считается
+fn estimate_eta(elapsed: Duration, progress: f32) -> Option<u64> {
+    if !(1.0..100.0).contains(&progress) {
+        return None;
+    }
+    let elapsed_ms = elapsed.as_millis() as f64;
+    let progress = progress as f64;
+    Some((elapsed_ms * (100.0 - progress) / progress) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(record: &str, stage: ExportStage, value: f32) -> ExportProgress {
+        ExportProgress {
+            stage,
+            progress: value,
+            message: String::new(),
+            record_name: Some(record.to_string()),
+            elapsed_ms: None,
+            eta_ms: None,
+            job_id: None,
+        }
+    }
+
+    #[test]
+    fn throttles_per_record_but_keeps_stage_changes() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let sent = sent.clone();
+            throttled(Arc::new(move |p: ExportProgress| {
+                sent.lock()
+                    .unwrap()
+                    .push((p.record_name.unwrap(), p.stage, p.progress))
+            }))
+        };
+
+        callback(progress("a", ExportStage::Encoding, 10.0));
+        callback(progress("a", ExportStage::Encoding, 11.0));
+        callback(progress("b", ExportStage::Encoding, 10.0));
+        callback(progress("a", ExportStage::Encoding, 12.0));
+        callback(progress("a", ExportStage::Completed, 100.0));
+        std::thread::sleep(PROGRESS_INTERVAL);
+        callback(progress("b", ExportStage::Encoding, 50.0));
+
+        let sent = sent.lock().unwrap();
+        let sent: Vec<_> = sent
+            .iter()
+            .map(|(record, stage, value)| (record.as_str(), *stage, *value))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                ("a", ExportStage::Encoding, 10.0),
+                ("b", ExportStage::Encoding, 10.0),
+                ("a", ExportStage::Completed, 100.0),
+                ("b", ExportStage::Encoding, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn eta_is_linear_and_unknown_at_the_edges() {
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(estimate_eta(elapsed, 0.0), None);
+        assert_eq!(estimate_eta(elapsed, 0.5), None);
+        assert_eq!(estimate_eta(elapsed, 100.0), None);
+        assert_eq!(estimate_eta(elapsed, 25.0), Some(30_000));
+        assert_eq!(estimate_eta(Duration::ZERO, 50.0), Some(0));
+    }
+}
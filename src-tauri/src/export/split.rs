@@ -0,0 +1,247 @@
+use anyhow::{bail, Result};
+use std::ops::Range;
+
+use crate::audio::timeline::Marker;
+use crate::audio::*;
+
+/// Запас на заголовки, теги и неравномерность битрейта в режиме `size`
+const SIZE_MARGIN: f64 = 0.97;
+/// Верхняя граница битрейта VBR (MP3 V0, Vorbis q10, AAC), кбит/с
+const VBR_MAX_KBPS: f64 = 320.0;
+/// Самая короткая часть, которую имеет смысл создавать, в секундах
+const MIN_PART_SECONDS: f64 = 1.0;
+
+/// Часть записи, кодируемая в отдельный файл
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// Сэмплы записи
+    pub range: Range<usize>,
+    /// Объявление части в режиме `arrangement`
+    pub marker: Option<Marker>,
+}
+
+/// Делит запись на части по режиму разбиения. Для `SplitMode::None`
+/// возвращает пустой список: запись пишется одним файлом. Пустую запись
+/// разбить нельзя — это ошибка, а не файл без звука.
+pub fn plan(settings: &ExportSettings, record: &RenderedRecord) -> Result<Vec<Part>> {
+    let len = record.samples.len();
+    let parts = match settings.split {
+        SplitMode::None => return Ok(Vec::new()),
+        _ if len == 0 => bail!("Запись пуста, разбивать нечего"),
+        SplitMode::Time { minutes } => {
+            if minutes == 0 {
+                bail!("Длина части должна быть не меньше минуты");
+            }
+            fixed_parts(len, minutes as usize * 60 * record.sample_rate as usize)?
+        }
+        SplitMode::Size { megabytes } => {
+            let seconds = megabytes as f64 * 1024.0 * 1024.0 * SIZE_MARGIN
+                / max_bytes_per_second(settings, record.sample_rate);
+            if seconds < MIN_PART_SECONDS {
+                bail!(
+                    "Лимит {megabytes} МБ слишком мал для формата {}",
+                    settings.extension
+                );
+            }
+            fixed_parts(len, (seconds * record.sample_rate as f64) as usize)?
+        }
+        SplitMode::Arrangement => {
+            if record.markers.is_empty() {
+                bail!("В записи нет объявлений для разбиения");
+            }
+            record
+                .markers
+                .iter()
+                .map(|marker| Part {
+                    range: marker.range.clone(),
+                    marker: Some(marker.clone()),
+                })
+                .collect()
+        }
+    };
+    Ok(parts)
+}
+
+/// Части равной длины; последняя может быть короче
+fn fixed_parts(len: usize, part_len: usize) -> Result<Vec<Part>> {
+    if part_len == 0 {
+        bail!("Длина части должна быть больше нуля");
+    }
+    Ok((0..len)
+        .step_by(part_len)
+        .map(|start| Part {
+            range: start..(start + part_len).min(len),
+            marker: None,
+        })
+        .collect())
+}
+
+/// Оценка размера секунды звука сверху (моно)
+fn max_bytes_per_second(settings: &ExportSettings, sample_rate: u32) -> f64 {
+    let bitrate = settings.bitrate as f64;
+    let kbps = match settings.extension.as_str() {
+        // Lossless сжимает не хуже несжатого PCM
+        "wav" | "flac" | "alac" => {
+            let bits = settings.bit_depth.integer_bits().unwrap_or(32);
+            return sample_rate as f64 * bits as f64 / 8.0;
+        }
+        "opus" => bitrate,
+        _ => match settings.mode {
+            BitrateMode::Cbr => bitrate,
+            // Средний битрейт ABR местами превышается
+            BitrateMode::Abr => bitrate * 1.25,
+            BitrateMode::Vbr => VBR_MAX_KBPS,
+        },
+    };
+    kbps * 1000.0 / 8.0
+}
+
+/// Имя файла части: `{record}_{NNN}` и название объявления, если оно есть
+pub fn part_file_name(record_name: &str, index: usize, part: &Part, extension: &str) -> String {
+    match &part.marker {
+        Some(marker) => format!(
+            "{record_name}_{:03}_{}.{extension}",
+            index + 1,
+            sanitize(&marker.title)
+        ),
+        None => format!("{record_name}_{:03}.{extension}", index + 1),
+    }
+}
+
+/// Название источника как часть имени файла: без запрещённых в Windows символов
+pub fn sanitize(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.');
+    if cleaned.is_empty() {
+        "clip".to_string()
+    } else {
+        cleaned.chars().take(60).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Частота, при которой секунда — сто сэмплов
+    const RATE: u32 = 100;
+
+    fn settings(extension: &str, split: SplitMode) -> ExportSettings {
+        let mut settings: ExportSettings = serde_json::from_str(&format!(
+            r#"{{"extension":"{extension}","bitrate":128,"mode":"cbr"}}"#
+        ))
+        .unwrap();
+        settings.split = split;
+        settings
+    }
+
+    fn marker(title: &str, range: Range<usize>) -> Marker {
+        Marker {
+            arrangement_id: title.into(),
+            type_id: None,
+            source_id: title.into(),
+            title: title.into(),
+            range,
+            source_offset: 0,
+            gain: 1.0,
+            fade_in: false,
+            fade_out: false,
+        }
+    }
+
+    fn record(seconds: usize, markers: Vec<Marker>) -> RenderedRecord {
+        RenderedRecord {
+            samples: vec![0.0; seconds * RATE as usize],
+            markers,
+            sample_rate: RATE,
+        }
+    }
+
+    fn ranges(parts: &[Part]) -> Vec<Range<usize>> {
+        parts.iter().map(|part| part.range.clone()).collect()
+    }
+
+    #[test]
+    fn time_parts_cover_record() {
+        let split = SplitMode::Time { minutes: 1 };
+        let parts = plan(&settings("mp3", split), &record(150, Vec::new())).unwrap();
+        assert_eq!(ranges(&parts), vec![0..6000, 6000..12000, 12000..15000]);
+
+        // Ровно две минуты — две части без пустого хвоста
+        let parts = plan(&settings("mp3", split), &record(120, Vec::new())).unwrap();
+        assert_eq!(ranges(&parts), vec![0..6000, 6000..12000]);
+
+        let zero = settings("mp3", SplitMode::Time { minutes: 0 });
+        assert!(plan(&zero, &record(150, Vec::new())).is_err());
+    }
+
+    #[test]
+    fn size_parts_fit_estimate() {
+        // 128 кбит/с CBR: 1 МБ с запасом — 63.57 с
+        let split = SplitMode::Size { megabytes: 1 };
+        let parts = plan(&settings("mp3", split), &record(150, Vec::new())).unwrap();
+        assert_eq!(ranges(&parts), vec![0..6356, 6356..12712, 12712..15000]);
+
+        let tiny = settings("mp3", SplitMode::Size { megabytes: 0 });
+        assert!(plan(&tiny, &record(150, Vec::new())).is_err());
+    }
+
+    #[test]
+    fn arrangement_parts_follow_markers() {
+        let markers = vec![marker("Звонок", 100..300), marker("Гимн", 500..900)];
+        let settings = settings("mp3", SplitMode::Arrangement);
+        let parts = plan(&settings, &record(10, markers.clone())).unwrap();
+        assert_eq!(ranges(&parts), vec![100..300, 500..900]);
+        assert_eq!(parts[1].marker.as_ref(), Some(&markers[1]));
+
+        assert!(plan(&settings, &record(10, Vec::new())).is_err());
+    }
+
+    #[test]
+    fn empty_record_is_not_split() {
+        let none = settings("mp3", SplitMode::None);
+        assert!(plan(&none, &record(0, Vec::new())).unwrap().is_empty());
+        for split in [
+            SplitMode::Time { minutes: 1 },
+            SplitMode::Size { megabytes: 1 },
+            SplitMode::Arrangement,
+        ] {
+            assert!(plan(&settings("mp3", split), &record(0, Vec::new())).is_err());
+        }
+        assert!(fixed_parts(100, 0).is_err());
+    }
+
+    #[test]
+    fn part_names() {
+        let plain = Part {
+            range: 0..10,
+            marker: None,
+        };
+        assert_eq!(part_file_name("monday", 0, &plain, "mp3"), "monday_001.mp3");
+        let named = Part {
+            range: 0..10,
+            marker: Some(marker("Звонок: на урок?", 0..10)),
+        };
+        assert_eq!(
+            part_file_name("monday", 11, &named, "flac"),
+            "monday_012_Звонок_ на урок_.flac"
+        );
+    }
+
+    #[test]
+    fn sanitize_titles() {
+        assert_eq!(sanitize(r#"a<b>c:d"e/f\g|h?i*j"#), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(sanitize("tab\there"), "tab_here");
+        assert_eq!(sanitize("  Звонок.. "), "Звонок");
+        assert_eq!(sanitize(""), "clip");
+        assert_eq!(sanitize(" ... "), "clip");
+        assert_eq!(sanitize(&"я".repeat(100)), "я".repeat(60));
+    }
+}
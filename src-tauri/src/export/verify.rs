@@ -0,0 +1,244 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use super::metadata::Chapter;
+use crate::audio::*;
+
+/// Допустимое расхождение длительности: задержка и добивка кадров
+/// MP3/AAC/Opus, которые декодер не отрезает
+const DURATION_TOLERANCE_MS: u64 = 150;
+/// RMS, ниже которого объявление в записи считается тишиной (−60 dBFS)
+const SILENCE_FLOOR: f32 = 0.001;
+/// Во сколько раз RMS в файле может быть тише отрендеренного (−20 dB)
+const SILENCE_RATIO: f32 = 0.1;
+/// Уровень и длина серии сэмплов, считающейся клиппингом
+const CLIP_LEVEL: f32 = 0.999;
+const CLIP_RUN: usize = 3;
+/// Объявления короче этого не проверяются: их сдвигает задержка кодека
+const MIN_CHECK_MS: u64 = 100;
+
+/// Проверяет записанный файл: декодирует его Symphonia, сверяет
+/// длительность с `samples`, ищет тишину и клиппинг на местах объявлений
+/// `chapters` и считает SHA-256.
+///
+/// Ошибка — файл не декодируется или его длина не совпадает с записью;
+/// подозрительные места объявлений попадают в предупреждения. Форматы,
+/// которые Symphonia не читает (RF64, Opus, HE-AAC), проверяются только
+/// контрольной суммой.
+pub fn verify_output(
+    path: &Path,
+    settings: &ExportSettings,
+    samples: &[f32],
+    chapters: &[Chapter],
+    sample_rate: u32,
+) -> Result<Verification> {
+    let ms = |position: usize| position as u64 * 1000 / sample_rate as u64;
+    let position = |ms: u64| (ms * sample_rate as u64 / 1000) as usize;
+    let mut verification = Verification {
+        file: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        duration_ms: None,
+        expected_ms: ms(samples.len()),
+        sha256: sha256(path)?,
+        warnings: Vec::new(),
+    };
+
+    let mut form = [0u8; 4];
+    std::io::Read::read_exact(&mut std::fs::File::open(path)?, &mut form)?;
+    let undecodable = match settings.extension.as_str() {
+        _ if &form == b"RF64" => Some("RF64"),
+        // В Symphonia 0.5 нет декодера Opus, а AAC декодируется только LC
+        "opus" => Some("Opus"),
+        "m4a" if settings.aac.profile == AacProfile::He => Some("HE-AAC"),
+        _ => None,
+    };
+    if let Some(format) = undecodable {
+        verification.warnings.push(format!(
+            "{format} не декодируется, проверена только контрольная сумма"
+        ));
+        return Ok(verification);
+    }
+
+    let decoded = AudioProcessor::new()
+        .decode_audio_file(&path.to_string_lossy())
+        .context("Записанный файл не декодируется")?;
+    let duration_ms = ms(decoded.len());
+    verification.duration_ms = Some(duration_ms);
+    if duration_ms.abs_diff(verification.expected_ms) > DURATION_TOLERANCE_MS {
+        bail!(
+            "Длительность файла {} мс вместо {} мс",
+            duration_ms,
+            verification.expected_ms
+        );
+    }
+
+    for chapter in chapters {
+        if chapter.end_ms - chapter.start_ms < MIN_CHECK_MS {
+            continue;
+        }
+        // Глава может выходить за конец записи целиком (округление, разбиение)
+        let end = position(chapter.end_ms).min(samples.len());
+        let range = position(chapter.start_ms).min(end)..end;
+        let Some(written) = decoded.get(range.start..range.end.min(decoded.len())) else {
+            continue;
+        };
+        let expected = rms(&samples[range.clone()]);
+        let actual = rms(written);
+        if expected > SILENCE_FLOOR && actual < expected * SILENCE_RATIO {
+            verification.warnings.push(format!(
+                "Тишина на месте объявления «{}» ({})",
+                chapter.title,
+                super::metadata::clock(chapter.start_ms)
+            ));
+        }
+        if let Some(at) = clipping(written) {
+            verification.warnings.push(format!(
+                "Клиппинг в объявлении «{}» ({})",
+                chapter.title,
+                super::metadata::clock(chapter.start_ms + ms(at))
+            ));
+        }
+    }
+
+    Ok(verification)
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Не удалось открыть {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    Ok(hex)
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples.iter().map(|&s| s as f64 * s as f64).sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+/// Начало первой серии из `CLIP_RUN` сэмплов на пределе шкалы
+fn clipping(samples: &[f32]) -> Option<usize> {
+    let mut run = 0;
+    for (i, sample) in samples.iter().enumerate() {
+        if sample.abs() >= CLIP_LEVEL {
+            run += 1;
+            if run == CLIP_RUN {
+                return Some(i + 1 - CLIP_RUN);
+            }
+        } else {
+            run = 0;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(extension: &str) -> ExportSettings {
+        serde_json::from_str(&format!(r#"{{"extension":"{extension}","bitrate":128}}"#)).unwrap()
+    }
+
+    fn chapter(title: &str, start_ms: u64, end_ms: u64) -> Chapter {
+        Chapter {
+            title: title.into(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("verify-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn rms_of_signal() {
+        assert_eq!(rms(&[]), 0.0);
+        assert_eq!(rms(&[0.5, -0.5, 0.5, -0.5]), 0.5);
+        assert!((rms(&[1.0, 0.0]) - 0.5f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clipping_needs_a_run() {
+        assert_eq!(
+            clipping(&[0.5, 1.0, 1.0, 0.5, -1.0, -0.9995, 1.0, 0.2]),
+            Some(4)
+        );
+        assert_eq!(clipping(&[1.0, 1.0, 0.0, 1.0, 1.0]), None);
+        assert_eq!(clipping(&[]), None);
+    }
+
+    #[test]
+    fn undecodable_formats_are_only_hashed() {
+        let mut he_aac = settings("m4a");
+        he_aac.aac.profile = AacProfile::He;
+        let cases = [
+            ("a.opus", settings("opus"), b"OggS".as_slice(), "Opus"),
+            ("a.m4a", he_aac, b"\0\0\0\x20ftyp".as_slice(), "HE-AAC"),
+            (
+                "a.wav",
+                settings("wav"),
+                b"RF64\xff\xff\xff\xff".as_slice(),
+                "RF64",
+            ),
+        ];
+        for (name, settings, contents, format) in cases {
+            let path = temp_file(name, contents);
+            let verification = verify_output(&path, &settings, &[0.0; 100], &[], 100).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(verification.duration_ms, None);
+            assert_eq!(verification.expected_ms, 1000);
+            assert_eq!(verification.sha256.len(), 64);
+            assert_eq!(
+                verification.warnings,
+                vec![format!(
+                    "{format} не декодируется, проверена только контрольная сумма"
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn chapters_past_record_end_are_skipped() {
+        let processor = AudioProcessor::new();
+        let rate = processor.sample_rate();
+        let samples: Vec<f32> = (0..rate)
+            .map(|i| if i % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let path = std::env::temp_dir().join(format!("verify-test-{}.wav", std::process::id()));
+        processor
+            .save_as_wav(&samples, &path.to_string_lossy())
+            .unwrap();
+
+        let chapters = [
+            chapter("Звонок", 200, 800),
+            // Начинается после конца записи
+            chapter("Гимн", 5_000, 6_000),
+        ];
+        let verification =
+            verify_output(&path, &settings("wav"), &samples, &chapters, rate).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(verification.duration_ms, Some(1000));
+        assert!(
+            verification.warnings.is_empty(),
+            "{:?}",
+            verification.warnings
+        );
+    }
+}
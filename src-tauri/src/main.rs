@@ -2,16 +2,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tauri::{Emitter, Manager, State};
 
 mod audio;
+mod export;
 use audio::*;
+use export::jobs::{ExportJob, JobQueue};
 
 // Функция для получения пути к встроенному FFmpeg
-fn get_ffmpeg_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+fn bundled_ffmpeg_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
     #[cfg(target_os = "windows")]
     let ffmpeg_name = "ffmpeg.exe";
     #[cfg(target_os = "macos")]
@@ -87,204 +91,471 @@ fn get_ffmpeg_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
     }
 }
 
-// Состояние для отслеживания прогресса
+// Состояние для отслеживания заданий экспорта
 #[derive(Default)]
 struct AppState {
-    // Поле оставлено для будущего использования
+    /// Очередь заданий и флаги отмены всех выполняющихся экспортов
+    export_jobs: JobQueue,
+    /// Путь к FFmpeg из настроек пользователя (проверяется первым)
+    ffmpeg_path: Mutex<Option<PathBuf>>,
+    /// Результат последнего поиска и проверки FFmpeg
+    ffmpeg: Mutex<Option<FfmpegInfo>>,
+    /// Временный файл последнего предпрослушивания
+    previews: export::preview::PreviewFiles,
+}
+
+/// Настройки приложения, которые сохраняются между запусками
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AppSettings {
+    /// Путь к FFmpeg, выбранный пользователем
+    #[serde(default)]
+    ffmpeg_path: Option<PathBuf>,
+}
+
+/// Файл настроек в папке конфигурации приложения
+fn settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path()
+        .app_config_dir()
+        .map(|dir| dir.join("settings.json"))
+        .map_err(|e| format!("Не удалось получить папку настроек: {e}"))
+}
+
+/// Читает настройки приложения; при ошибке — настройки по умолчанию
+fn load_settings(app_handle: &tauri::AppHandle) -> AppSettings {
+    let Ok(path) = settings_path(app_handle).inspect_err(|e| log::warn!("{e}")) else {
+        return AppSettings::default();
+    };
+    if !path.exists() {
+        return AppSettings::default();
+    }
+
+    fs::read(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| Ok(serde_json::from_slice(&data)?))
+        .unwrap_or_else(|e| {
+            log::warn!("Не удалось прочитать настройки {}: {e:#}", path.display());
+            AppSettings::default()
+        })
+}
+
+fn save_settings(app_handle: &tauri::AppHandle, settings: &AppSettings) -> Result<(), String> {
+    let path = settings_path(app_handle)?;
+    let save = || -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        export::output::write(&path, serde_json::to_vec_pretty(settings)?)
+    };
+    save().map_err(|e| format!("Не удалось сохранить настройки {}: {e:#}", path.display()))
+}
+
+/// Выполняет поиск и проверку FFmpeg в отдельном потоке, не занимая поток команд
+async fn off_thread<T, F>(app_handle: &tauri::AppHandle, work: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&tauri::AppHandle, &AppState) -> Result<T, String> + Send + 'static,
+{
+    let app_handle = app_handle.clone();
+    tokio::task::spawn_blocking(move || work(&app_handle, &app_handle.state::<AppState>()))
+        .await
+        .map_err(|e| format!("{e}"))?
+}
+
+/// Находит FFmpeg (настройки пользователя, bundle, PATH) и кэширует результат проверки
+fn get_ffmpeg(app_handle: &tauri::AppHandle, state: &AppState) -> Result<FfmpegInfo, String> {
+    if let Some(info) = state.ffmpeg.lock().unwrap().clone() {
+        return Ok(info);
+    }
+
+    let bundled = bundled_ffmpeg_path(app_handle)
+        .inspect_err(|e| log::warn!("{e}"))
+        .ok();
+    let configured = state.ffmpeg_path.lock().unwrap().clone();
+    let candidates = export::discovery::candidates(configured.as_deref(), bundled);
+    let info = export::discovery::locate(&candidates).map_err(|e| format!("{e:#}"))?;
+
+    *state.ffmpeg.lock().unwrap() = Some(info.clone());
+    Ok(info)
+}
+
+/// Кодировщик для формата из настроек экспорта: FFmpeg или встроенный
+fn encoder_for(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    settings: &ExportSettings,
+) -> Result<export::Encoder, String> {
+    export::Encoder::select(settings, || get_ffmpeg(app_handle, state))
+        .map_err(|e| format!("{e:#}"))
+}
+
+/// `encoder_for` для команд: FFmpeg проверяется в отдельном потоке
+async fn select_encoder(
+    app_handle: &tauri::AppHandle,
+    settings: &ExportSettings,
+) -> Result<export::Encoder, String> {
+    let settings = settings.clone();
+    off_thread(app_handle, move |app_handle, state| {
+        encoder_for(app_handle, state, &settings)
+    })
+    .await
+}
+
+/// Отправляет прогресс экспорта в интерфейс с id задания
+fn emit_progress(app_handle: &tauri::AppHandle, job_id: &str, mut progress: ExportProgress) {
+    progress.job_id = Some(job_id.to_string());
+    let _ = app_handle.emit("export_progress", &progress);
+}
+
+/// Выполняет работу команды в отдельном потоке как отменяемое задание:
+/// регистрирует флаг отмены, отправляет прогресс (не чаще `throttled`) и ошибку
+/// в интерфейс с id задания
+async fn run_job<T, F>(
+    state: &AppState,
+    app_handle: &tauri::AppHandle,
+    job_id: Option<String>,
+    record_name: Option<String>,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&CancelToken, &dyn Fn(ExportProgress)) -> Result<T> + Send + 'static,
+{
+    let job_id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let cancel = state
+        .export_jobs
+        .register(&job_id)
+        .map_err(|e| format!("{e:#}"))?;
+
+    let progress_callback = {
+        let app_handle = app_handle.clone();
+        let job_id = job_id.clone();
+        export::progress::throttled(Arc::new(move |progress| {
+            emit_progress(&app_handle, &job_id, progress)
+        }))
+    };
+
+    let result = tokio::task::spawn_blocking(move || work(&cancel, &*progress_callback))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+
+    state.export_jobs.unregister(&job_id);
+
+    result.map_err(|e| {
+        emit_progress(
+            app_handle,
+            &job_id,
+            export::failure_progress(&e, record_name.as_deref()),
+        );
+        format!("{e:#}")
+    })
+}
+
+/// Пути записанных файлов для ответа команды
+fn path_strings(paths: Vec<PathBuf>) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
 }
 
 #[tauri::command]
 async fn export_audio(
     request: ExportRequest,
     output_dir: String,
+    job_id: Option<String>,
     app_handle: tauri::AppHandle,
-    _state: State<'_, AppState>,
+    state: State<'_, AppState>,
 ) -> Result<String, String> {
-    let processor = AudioProcessor::new();
-
-    let arrangements = request
-        .arrangements
-        .get(&request.record_name)
-        .ok_or_else(|| "Record not found".to_string())?;
-    let time_record = request
-        .time_of_records
-        .get(&request.record_name)
-        .ok_or_else(|| "Time record not found".to_string())?;
-
-    let app_handle_clone = app_handle.clone();
-    let progress_callback = move |progress: ExportProgress| {
-        let _ = app_handle_clone.emit("export_progress", &progress);
+    let encoder = select_encoder(&app_handle, &request.settings).await?;
+
+    let record_name = request.record_name.clone();
+    let exported = run_job(
+        &state,
+        &app_handle,
+        job_id,
+        Some(record_name),
+        move |cancel, progress_callback| {
+            let processor = AudioProcessor::new();
+            let audio_cache = processor.load_sources(
+                &request.sources,
+                Some(&request.record_name),
+                cancel,
+                progress_callback,
+            )?;
+            export::export_record(
+                &processor,
+                &request,
+                &request.record_name,
+                &audio_cache,
+                &PathBuf::from(&output_dir),
+                &encoder,
+                cancel,
+                progress_callback,
+            )
+        },
+    )
+    .await?;
+
+    Ok(exported.path.to_string_lossy().to_string())
+}
+
+/// Экспортирует несколько записей за один вызов с общим декодированием источников
+#[tauri::command]
+async fn export_all(
+    request: ExportRequest,
+    output_dir: String,
+    max_parallel: Option<usize>,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<RecordExportResult>, String> {
+    let encoder = select_encoder(&app_handle, &request.settings).await?;
+
+    let record_names = export::requested_records(&request);
+
+    let job_id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let cancel = state
+        .export_jobs
+        .register(&job_id)
+        .map_err(|e| format!("{e:#}"))?;
+
+    let progress_callback: export::ProgressCallback = {
+        let job_id = job_id.clone();
+        export::progress::throttled(Arc::new(move |progress| {
+            emit_progress(&app_handle, &job_id, progress)
+        }))
     };
 
-    let samples = processor
-        .render_record(
-            &request.record_name,
-            arrangements,
-            time_record,
-            &request.sources,
-            progress_callback,
-        )
-        .await
-        .map_err(|e| e.to_string())?;
+    let results = export::export_records(
+        request,
+        record_names,
+        PathBuf::from(output_dir),
+        encoder,
+        max_parallel.unwrap_or(export::DEFAULT_MAX_PARALLEL),
+        cancel,
+        progress_callback,
+    )
+    .await;
+
+    state.export_jobs.unregister(&job_id);
+    Ok(results)
+}
+
+/// Ставит экспорт записей в очередь и сразу возвращает id задания
+#[tauri::command]
+async fn submit_export(
+    request: ExportRequest,
+    output_dir: String,
+    max_parallel: Option<usize>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let record_names = export::requested_records(&request);
+
+    // FFmpeg ищется уже в задании, чтобы id вернулся сразу
+    let encoder: export::jobs::EncoderResolver = {
+        let app_handle = app_handle.clone();
+        let settings = request.settings.clone();
+        Box::new(move || {
+            encoder_for(&app_handle, &app_handle.state::<AppState>(), &settings)
+                .map_err(anyhow::Error::msg)
+        })
+    };
 
-    let final_path = PathBuf::from(&output_dir).join(format!(
-        "{}.{}",
-        request.record_name, request.settings.extension
-    ));
+    let progress_callback: export::ProgressCallback = Arc::new(move |progress| {
+        let _ = app_handle.emit("export_progress", &progress);
+    });
+
+    Ok(state.export_jobs.submit(
+        request,
+        record_names,
+        PathBuf::from(output_dir),
+        encoder,
+        max_parallel.unwrap_or(export::DEFAULT_MAX_PARALLEL),
+        progress_callback,
+    ))
+}
+
+#[tauri::command]
+async fn list_jobs(state: State<'_, AppState>) -> Result<Vec<ExportJob>, String> {
+    Ok(state.export_jobs.list())
+}
+
+#[tauri::command]
+async fn get_job(job_id: String, state: State<'_, AppState>) -> Result<ExportJob, String> {
+    state
+        .export_jobs
+        .get(&job_id)
+        .ok_or_else(|| format!("Задание экспорта {job_id} не найдено"))
+}
+
+/// Сколько заданий очереди выполняется одновременно
+#[tauri::command]
+async fn set_export_concurrency(
+    max_running: usize,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state.export_jobs.set_max_running(max_running);
+    Ok(())
+}
+
+/// Отменяет экспорт или задание очереди; недописанный файл удаляется
+#[tauri::command]
+async fn cancel_export(job_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    if state.export_jobs.cancel(&job_id) {
+        Ok(())
+    } else {
+        Err(format!("Задание экспорта {job_id} не найдено"))
+    }
+}
 
-    export_samples_with_ffmpeg(
-        &samples,
-        final_path.to_str().unwrap(),
-        &request.settings,
+/// Рендерит окно записи во временный WAV и возвращает путь к нему
+#[tauri::command]
+async fn render_preview(
+    request: ExportRequest,
+    record_name: String,
+    window: PreviewWindow,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let temp_dir = temp_dir();
+    let path = run_job(
+        &state,
         &app_handle,
+        job_id,
+        Some(record_name.clone()),
+        move |cancel, progress_callback| {
+            export::preview::render_preview(
+                &request,
+                &record_name,
+                &window,
+                &temp_dir,
+                cancel,
+                progress_callback,
+            )
+        },
     )
-    .map_err(|e| e.to_string())?;
+    .await?;
+    state.previews.replace(path.clone());
 
-    let final_path_str = final_path.to_string_lossy().to_string();
+    Ok(path.to_string_lossy().to_string())
+}
 
-    let _ = app_handle.emit(
-        "export_progress",
-        &ExportProgress {
-            stage: "completed".to_string(),
-            progress: 100.0,
-            message: "Экспорт завершен".to_string(),
-            record_name: Some(request.record_name.clone()),
+/// Экспортирует размещения объявлений записи (метки Audacity, CSV, EDL)
+#[tauri::command]
+async fn export_timeline(
+    request: ExportRequest,
+    record_name: String,
+    formats: Vec<TimelineFormat>,
+    output_dir: String,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    run_job(
+        &state,
+        &app_handle,
+        job_id,
+        Some(record_name.clone()),
+        move |cancel, progress_callback| {
+            export::placements::export_timeline(
+                &request,
+                &record_name,
+                &formats,
+                &PathBuf::from(output_dir),
+                cancel,
+                progress_callback,
+            )
         },
-    );
-
-    Ok(final_path_str)
+    )
+    .await
+    .map(path_strings)
 }
 
-fn export_samples_with_ffmpeg(
-    samples: &[f32],
-    output_path: &str,
-    settings: &ExportSettings,
-    app_handle: &tauri::AppHandle,
-) -> Result<()> {
-    use std::process::{Command, Stdio};
-
-    let sample_rate = 44100.0;
-    let expected_duration = samples.len() as f64 / sample_rate;
-    let hours = (expected_duration / 3600.0) as u32;
-    let minutes = ((expected_duration % 3600.0) / 60.0) as u32;
-    let seconds = (expected_duration % 60.0) as u32;
-
-    log::info!(
-        "Экспорт {} сэмплов в {}, длительность: {}:{:02}:{:02}",
-        samples.len(),
-        settings.extension,
-        hours,
-        minutes,
-        seconds
-    );
-
-    let ffmpeg_path = get_ffmpeg_path(app_handle)
-        .map_err(|e| anyhow::anyhow!("Ошибка получения пути к FFmpeg: {}", e))?;
-
-    let _ = app_handle.emit(
-        "export_progress",
-        &ExportProgress {
-            stage: "encoding".to_string(),
-            progress: 80.0,
-            message: format!("Кодирование в {}", settings.extension),
-            record_name: None,
+/// Экспортирует плейлисты записи со ссылками на исходные файлы (M3U8, PLS, XSPF)
+#[tauri::command]
+async fn export_playlists(
+    request: ExportRequest,
+    record_name: String,
+    formats: Vec<PlaylistFormat>,
+    output_dir: String,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    run_job(
+        &state,
+        &app_handle,
+        job_id,
+        Some(record_name.clone()),
+        move |cancel, progress_callback| {
+            export::playlist::export_playlists(
+                &request,
+                &record_name,
+                &formats,
+                &PathBuf::from(output_dir),
+                cancel,
+                progress_callback,
+            )
         },
-    );
-
-    let mut args = vec!["-f", "f32le", "-ar", "44100", "-ac", "1", "-i", "pipe:0"];
-    let bitrate = format!("{}k", settings.bitrate);
-
-    match settings.extension.as_str() {
-        "mp3" => args.extend_from_slice(&[
-            "-codec:a",
-            "libmp3lame",
-            "-b:a",
-            &bitrate,
-            "-cbr",
-            "1",
-            "-reservoir",
-            "0",
-        ]),
-        "ogg" => args.extend_from_slice(&[
-            "-codec:a",
-            "libvorbis",
-            "-b:a",
-            &bitrate,
-            "-minrate",
-            &bitrate,
-            "-maxrate",
-            &bitrate,
-        ]),
-        "flac" => args.extend_from_slice(&[
-            "-codec:a",
-            "flac",
-            "-compression_level",
-            "5",
-            "-exact_rice_parameters",
-            "1",
-        ]),
-        "wav" => args.extend_from_slice(&["-codec:a", "pcm_s16le"]),
-        other => anyhow::bail!("Неподдерживаемый формат: {}", other),
-    }
+    )
+    .await
+    .map(path_strings)
+}
 
-    args.extend_from_slice(&[
-        "-avoid_negative_ts",
-        "make_zero",
-        "-fflags",
-        "+genpts",
-        "-y",
-        output_path,
-    ]);
-
-    let mut child = Command::new(&ffmpeg_path)
-        .args(&args)
-        .stdin(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| anyhow::anyhow!("Не удалось запустить FFmpeg: {}", e))?;
-
-    let mut stdin = child
-        .stdin
-        .take()
-        .ok_or_else(|| anyhow::anyhow!("Нет stdin у FFmpeg"))?;
-
-    // ОПТИМИЗАЦИЯ: записываем большими чанками вместо по одному сэмплу
-    let chunk_size = 44100 * 4; // 4 секунды за раз
-    let total_chunks = samples.len().div_ceil(chunk_size);
-
-    for (i, chunk) in samples.chunks(chunk_size).enumerate() {
-        // Собираем все сэмплы чанка в один буфер
-        let mut buffer = Vec::with_capacity(chunk.len() * 4); // 4 байта на float32
-        for &sample in chunk {
-            buffer.extend_from_slice(&sample.to_le_bytes());
-        }
+/// Компактный экспорт записи: фрагменты по одному разу и расписание их
+/// звучания (JSON и M3U) вместо часов тишины
+#[tauri::command]
+async fn export_event_list(
+    request: ExportRequest,
+    output_dir: String,
+    playlists: Option<Vec<PlaylistFormat>>,
+    job_id: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let encoder = select_encoder(&app_handle, &request.settings).await?;
 
-        // Записываем весь буфер за один вызов
-        stdin
-            .write_all(&buffer)
-            .map_err(|e| anyhow::anyhow!("Ошибка записи чанка: {}", e))?;
-
-        let prog = 80.0 + (i as f32 / total_chunks as f32) * 15.0;
-        let _ = app_handle.emit(
-            "export_progress",
-            &ExportProgress {
-                stage: "encoding".to_string(),
-                progress: prog,
-                message: format!("{}: {:.1}%", settings.extension, prog - 80.0),
-                record_name: None,
-            },
-        );
-    }
-    drop(stdin);
-
-    let out = child
-        .wait_with_output()
-        .map_err(|e| anyhow::anyhow!("FFmpeg завершился с ошибкой: {}", e))?;
-    if !out.status.success() {
-        let err = String::from_utf8_lossy(&out.stderr);
-        anyhow::bail!("FFmpeg: {}", err);
-    }
-    Ok(())
+    let record_name = request.record_name.clone();
+    run_job(
+        &state,
+        &app_handle,
+        job_id,
+        Some(record_name),
+        move |cancel, progress_callback| {
+            let processor = AudioProcessor::new();
+            let audio_cache = processor.load_sources(
+                &request.sources,
+                Some(&request.record_name),
+                cancel,
+                progress_callback,
+            )?;
+            export::event_list::export_event_list(
+                &processor,
+                &request,
+                &request.record_name,
+                &audio_cache,
+                &PathBuf::from(&output_dir),
+                &encoder,
+                &playlists.unwrap_or_default(),
+                cancel,
+                progress_callback,
+            )
+        },
+    )
+    .await
+    .map(path_strings)
+}
+
+#[tauri::command]
+async fn resolve_arrangements(
+    request: ExportRequest,
+    record_name: String,
+) -> Result<Vec<Arrangement>, String> {
+    schedule::resolve_record(&request, &record_name).map_err(|e| format!("{e:#}"))
 }
 
 #[tauri::command]
@@ -309,9 +580,14 @@ async fn select_audio_files() -> Result<Vec<String>, String> {
         .collect())
 }
 
+/// Каталог временных файлов приложения (загруженные файлы, предпрослушивание)
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join("ring_generator_temp")
+}
+
 #[tauri::command]
 async fn save_temp_file(file_name: String, file_data: Vec<u8>) -> Result<String, String> {
-    let temp_dir = std::env::temp_dir().join("ring_generator_temp");
+    let temp_dir = temp_dir();
     fs::create_dir_all(&temp_dir).map_err(|e| format!("Не удалось создать temp: {e}"))?;
     let path = temp_dir.join(&file_name);
     let mut f = fs::File::create(&path).map_err(|e| format!("Не удалось создать файл: {e}"))?;
@@ -320,11 +596,37 @@ async fn save_temp_file(file_name: String, file_data: Vec<u8>) -> Result<String,
     Ok(path.to_string_lossy().to_string())
 }
 
+/// Заново ищет FFmpeg и сообщает его версию и доступные форматы
+#[tauri::command]
+async fn check_ffmpeg_availability(app_handle: tauri::AppHandle) -> Result<FfmpegInfo, String> {
+    off_thread(&app_handle, |app_handle, state| {
+        state.ffmpeg.lock().unwrap().take();
+        get_ffmpeg(app_handle, state)
+    })
+    .await
+}
+
+/// Задаёт путь к FFmpeg из настроек пользователя (`None` — сбросить)
+/// и сохраняет его в настройках приложения
 #[tauri::command]
-async fn check_ffmpeg_availability(app_handle: tauri::AppHandle) -> Result<String, String> {
-    get_ffmpeg_path(&app_handle)
-        .map(|p| format!("FFmpeg найден: {}", p.display()))
-        .map_err(|e| e)
+async fn set_ffmpeg_path(
+    path: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<FfmpegInfo, String> {
+    let path = path.map(PathBuf::from);
+    off_thread(&app_handle, move |app_handle, state| {
+        if let Some(path) = &path {
+            export::discovery::probe(path).map_err(|e| format!("{}: {e:#}", path.display()))?;
+        }
+
+        let mut settings = load_settings(app_handle);
+        settings.ffmpeg_path = path.clone();
+        save_settings(app_handle, &settings)?;
+        *state.ffmpeg_path.lock().unwrap() = path;
+        state.ffmpeg.lock().unwrap().take();
+        get_ffmpeg(app_handle, state)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -336,14 +638,37 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_log::Builder::new().build())
         .manage(AppState::default())
+        .setup(|app| {
+            let settings = load_settings(app.handle());
+            *app.state::<AppState>().ffmpeg_path.lock().unwrap() = settings.ffmpeg_path;
+            export::preview::remove_stale(&temp_dir());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             export_audio,
+            export_all,
+            cancel_export,
+            submit_export,
+            list_jobs,
+            get_job,
+            set_export_concurrency,
+            resolve_arrangements,
+            render_preview,
+            export_timeline,
+            export_playlists,
+            export_event_list,
             select_output_directory,
             select_audio_files,
             save_temp_file,
             check_ffmpeg_availability,
+            set_ffmpeg_path,
             test_tauri_availability
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                app.state::<AppState>().previews.clear();
+            }
+        });
 }
@@ -1,4 +1,5 @@
 import { createContext } from "react";
+import { getMachineTimeZone } from "shared/time";
 import {
   AddInEachArrangementModalState,
   AdTypes,
@@ -17,12 +18,14 @@ import {
   DispatchCutModalState,
   DispatchExportSettings,
   DispatchPlayer,
+  DispatchProjectSettings,
   DispatchSources,
   DispatchTabs,
   DispatchTabsSync,
   DispatchTimeOfRecords,
   ExportSettings,
   Player,
+  ProjectSettings,
   Source,
   Tabs,
   TabsSync,
@@ -133,6 +136,16 @@ const DispatchTimeOfRecordsContext = createContext<DispatchTimeOfRecords>(
 const ActiveTabContext = createContext<string>("monday");
 const DispatchActiveTabContext = createContext<DispatchActiveTab>(() => {});
 
+// ProjectSettings
+const ProjectSettingsContext = createContext<ProjectSettings>({
+  recurrences: [],
+  timetables: [],
+  timeZone: getMachineTimeZone(),
+});
+const DispatchProjectSettingsContext = createContext<DispatchProjectSettings>(
+  () => {},
+);
+
 export {
   SourcesContext,
   DispatchSourcesContext,
@@ -162,4 +175,6 @@ export {
   DispatchExportSettingsContext,
   ActiveTabContext,
   DispatchActiveTabContext,
+  ProjectSettingsContext,
+  DispatchProjectSettingsContext,
 };
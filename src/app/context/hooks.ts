@@ -28,6 +28,8 @@ import {
   ExportSettingsContext,
   ActiveTabContext,
   DispatchActiveTabContext,
+  ProjectSettingsContext,
+  DispatchProjectSettingsContext,
 } from "./context";
 
 export const usePlayer = () => useContext(PlayerContext);
@@ -83,3 +85,7 @@ export const useDispatchExportSettings = () =>
 
 export const useActiveTab = () => useContext(ActiveTabContext);
 export const useDispatchActiveTab = () => useContext(DispatchActiveTabContext);
+
+export const useProjectSettings = () => useContext(ProjectSettingsContext);
+export const useDispatchProjectSettings = () =>
+  useContext(DispatchProjectSettingsContext);
@@ -19,12 +19,14 @@ import {
   DispatchCutModalStateContext,
   DispatchExportSettingsContext,
   DispatchPlayerContext,
+  DispatchProjectSettingsContext,
   DispatchSourcesContext,
   DispatchTabsContext,
   DispatchTabsSyncContext,
   DispatchTimeOfRecordsContext,
   ExportSettingsContext,
   PlayerContext,
+  ProjectSettingsContext,
   SourcesContext,
   TabsContext,
   TabsSyncContext,
@@ -40,6 +42,7 @@ import {
   CutModalState,
   ExportSettings,
   Player,
+  ProjectSettings,
   Source,
   Tabs,
   TabsSync,
@@ -47,6 +50,7 @@ import {
 } from "./types";
 import { v7 } from "uuid";
 import { dateTime } from "@gravity-ui/date-utils";
+import { getMachineTimeZone } from "shared/time";
 
 const ContextProvider = ({ children }: { children: ReactNode }) => {
   const [sources, setSources] = useState<Source[]>([]);
@@ -147,6 +151,12 @@ const ContextProvider = ({ children }: { children: ReactNode }) => {
 
   const [activeTab, setActiveTab] = useState<string>("monday");
 
+  const [projectSettings, setProjectSettings] = useState<ProjectSettings>({
+    recurrences: [],
+    timetables: [],
+    timeZone: getMachineTimeZone(),
+  });
+
   const toaster = new Toaster();
 
   return (
@@ -216,7 +226,19 @@ const ContextProvider = ({ children }: { children: ReactNode }) => {
                                                             <ActiveTabContext.Provider
                                                               value={activeTab}
                                                             >
-                                                              {children}
+                                                              <DispatchProjectSettingsContext.Provider
+                                                                value={
+                                                                  setProjectSettings
+                                                                }
+                                                              >
+                                                                <ProjectSettingsContext.Provider
+                                                                  value={
+                                                                    projectSettings
+                                                                  }
+                                                                >
+                                                                  {children}
+                                                                </ProjectSettingsContext.Provider>
+                                                              </DispatchProjectSettingsContext.Provider>
                                                             </ActiveTabContext.Provider>
                                                           </DispatchActiveTabContext.Provider>
                                                         </DispatchExportSettingsContext.Provider>
@@ -3,6 +3,47 @@ import { DateTime } from "@gravity-ui/date-utils";
 export type ExportSettings = {
   bitrate: number;
   extension: string;
+  // Кодировщик: FFmpeg, встроенный (WAV, FLAC) или auto — FFmpeg, если он есть
+  encoder?: "auto" | "ffmpeg" | "native";
+  // Режим битрейта MP3, Vorbis и AAC; quality (0–10) используется в VBR
+  mode?: "cbr" | "vbr" | "abr";
+  quality?: number;
+  // Разрядность WAV, FLAC и ALAC; 32f — только WAV
+  bit_depth?: "16" | "24" | "32f";
+  dither?: "none" | "tpdf" | "noise_shaped";
+  opus?: {
+    vbr?: "on" | "off" | "constrained";
+    application?: "audio" | "voip" | "lowdelay";
+  };
+  // Шаблоны тегов: {record}, {project}, {date}, {year}, {start}, {end}
+  tags?: {
+    enabled?: boolean;
+    title?: string | null;
+    artist?: string | null;
+    album?: string | null;
+    date?: string | null;
+    comment?: string | null;
+    custom?: Record<string, string>;
+  };
+  // Главы по объявлениям внутри файла и файл .cue рядом с ним
+  chapters?: {
+    enabled?: boolean;
+    cue?: boolean;
+  };
+  // Разбиение записи на файлы; рядом пишется {record}_index.json
+  split?:
+    | { mode: "none" }
+    | { mode: "time"; minutes: number }
+    | { mode: "arrangement" }
+    | { mode: "size"; megabytes: number };
+  // Декодировать записанный файл и сверить длительность, тишину и клиппинг
+  verify?: boolean;
+  // Существующий файл: заменить, сохранить оба (" (1)") или прервать экспорт
+  overwrite?: "overwrite" | "keep_both" | "fail";
+  aac?: {
+    // HE-AAC доступен только в FFmpeg с libfdk_aac
+    profile?: "lc" | "he";
+  };
 };
 
 export type Source = {
@@ -25,7 +66,12 @@ export type Arrangement = {
   id: string;
   fadeIn: boolean;
   fadeOut: boolean;
-  fixedTime: "end" | "start" | null;
+  fixedTime: "end" | "start" | "center" | null;
+  relativeTo?: {
+    arrangementId: Arrangement["id"];
+    edge: "start" | "end";
+    offsetMs: number;
+  } | null;
   loudness: number;
 };
 
@@ -33,6 +79,65 @@ export type Arrangements = {
   [key: string]: Arrangement[];
 };
 
+export type Weekday = "Mon" | "Tue" | "Wed" | "Thu" | "Fri" | "Sat" | "Sun";
+
+// Правило повторения; бэкенд разворачивает его в объявления при экспорте.
+// В интерфейсе правила пока не редактируются: они приходят только из данных
+// проекта (ProjectFileData.projectSettings) и передаются в экспорт как есть.
+// Время — "HH:MM:SS", даты — "YYYY-MM-DD"; окно every с from позже to
+// переходит через полночь
+export type RecurrenceRule = {
+  id: string;
+  typeId: Source["typeId"];
+  pattern:
+    | { kind: "every"; intervalMinutes: number; from: string; to: string }
+    | { kind: "at"; times: string[] };
+  // Пусто — правило действует во всех записях и во все дни
+  records?: string[];
+  weekdays?: Weekday[];
+  exceptTimes?: string[];
+  exceptDates?: string[];
+  // По умолчанию — длина обрезанного источника
+  durationMs?: number | null;
+  loudness: number | null;
+  fadeIn?: boolean;
+  fadeOut?: boolean;
+  fixedTime?: Arrangement["fixedTime"];
+};
+
+// Расписание уроков; бэкенд разворачивает его в звонки на урок и с урока.
+// Как и правила повторения, в интерфейсе пока не редактируется и приходит
+// только из данных проекта
+export type Timetable = {
+  id: string;
+  // "HH:MM:SS"
+  firstLessonStart: string;
+  lessonMinutes: number[];
+  // Перемена после каждого урока, кроме последнего
+  breakMinutes?: number[];
+  lessonStartTypeId?: AdType["value"] | null;
+  lessonEndTypeId?: AdType["value"] | null;
+  loudness: number | null;
+  fadeIn?: boolean;
+  fadeOut?: boolean;
+  // Например сокращённый день: уроки не длиннее lessonMinutes
+  variants?: {
+    name: string;
+    lessonMinutes: number;
+    breakMinutes?: number[] | null;
+  }[];
+  records: { record: string; variant?: string | null }[];
+};
+
+// Настройки проекта, которые сохраняются с проектом и уходят в запрос экспорта
+export type ProjectSettings = {
+  recurrences: RecurrenceRule[];
+  timetables: Timetable[];
+  // Пояс, в котором заданы время записей и правил (имя IANA, например "Europe/Moscow");
+  // выбирается в параметрах экспорта
+  timeZone: string;
+};
+
 export type Player = {
   isPlaying: "play" | "ready" | "pause" | "idle";
   type: "primary" | "secondary";
@@ -122,3 +227,6 @@ export type DispatchExportSettings = React.Dispatch<
   React.SetStateAction<ExportSettings>
 >;
 export type DispatchActiveTab = React.Dispatch<React.SetStateAction<string>>;
+export type DispatchProjectSettings = React.Dispatch<
+  React.SetStateAction<ProjectSettings>
+>;
@@ -1,14 +1,19 @@
 import { Card, Flex, Select, Text } from "@gravity-ui/uikit";
 import { typeExtSelectOptions, typeBitraitSelectOptions } from "shared/data";
+import { getTimeZoneOptions } from "shared/time";
 import SaveButton from "./buttons/SaveButton";
 import {
   useDispatchExportSettings,
+  useDispatchProjectSettings,
   useExportSettings,
+  useProjectSettings,
 } from "app/context/hooks";
 
 const Stage3 = () => {
   const exportSettings = useExportSettings();
   const dispatchExportSettings = useDispatchExportSettings();
+  const projectSettings = useProjectSettings();
+  const dispatchProjectSettings = useDispatchProjectSettings();
 
   function onUpdateBitrate(value: string[]) {
     dispatchExportSettings({
@@ -22,6 +27,12 @@ const Stage3 = () => {
       extension: value[0],
     });
   }
+  function onUpdateTimeZone(value: string[]) {
+    dispatchProjectSettings({
+      ...projectSettings,
+      timeZone: value[0],
+    });
+  }
   return (
     <Card spacing={{ p: "4" }}>
       <Flex direction={"column"} style={{ width: "max-content" }} gap={"2"}>
@@ -38,6 +49,13 @@ const Stage3 = () => {
           label="Битрейт"
           options={typeBitraitSelectOptions}
         />
+        <Select
+          value={[projectSettings.timeZone]}
+          onUpdate={onUpdateTimeZone}
+          label="Часовой пояс"
+          options={getTimeZoneOptions(projectSettings.timeZone)}
+          filterable
+        />
         <SaveButton />
       </Flex>
     </Card>
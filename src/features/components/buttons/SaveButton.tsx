@@ -3,6 +3,7 @@ import {
   useActiveTab,
   useArrangements,
   useExportSettings,
+  useProjectSettings,
   useSources,
   useTabs,
   useTimeOfRecords,
@@ -20,6 +21,7 @@ const SaveButton = () => {
   const arrangements = useArrangements();
   const timeOfRecords = useTimeOfRecords();
   const exportSettings = useExportSettings();
+  const projectSettings = useProjectSettings();
   const toaster = useToaster();
 
   const [loading, setLoading] = useState(false);
@@ -188,9 +190,10 @@ const SaveButton = () => {
       if (isTauriAvailable) {
         // ВСЕГДА используем Tauri когда приложение запущено как десктопное
         
-        // Проверяем доступность FFmpeg
+        // Проверяем доступность FFmpeg (WAV и FLAC кодируются и без него)
         const ffmpegCheck = await TauriAudioAPI.checkFFmpegAvailability();
-        if (!ffmpegCheck.available) {
+        const nativeFormat = ['wav', 'flac'].includes(exportSettings.extension);
+        if (!ffmpegCheck.available && !nativeFormat) {
           throw new Error(`${ffmpegCheck.message}\n\nИнструкции по установке FFmpeg см. в документации проекта.`);
         }
         
@@ -199,6 +202,7 @@ const SaveButton = () => {
           sources,
           currentTabArrangements,
           currentTabTimeRecord,
+          projectSettings,
           exportSettings,
           activeTab
         );
@@ -9,4 +9,7 @@ export const typeExtSelectOptions = [
   { value: "wav", content: "WAV" },
   { value: "ogg", content: "OGG" },
   { value: "flac", content: "FLAC" },
+  { value: "opus", content: "Opus" },
+  { value: "m4a", content: "AAC (M4A)" },
+  { value: "alac", content: "ALAC (M4A)" },
 ];
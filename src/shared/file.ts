@@ -4,8 +4,10 @@ import {
   AdType,
   Tab,
   TabsSync,
-  ExportSettings
+  ExportSettings,
+  ProjectSettings
 } from '../app/context/types';
+import { getMachineTimeZone } from './time';
 
 export interface ValidationResult {
   isValid: boolean;
@@ -52,6 +54,7 @@ export interface ProjectFileData {
   tabs: Tab[];
   tabsSync: TabsSync;
   exportSettings: ExportSettings;
+  projectSettings: ProjectSettings;
 }
 
 /**
@@ -198,6 +201,14 @@ function migrateProjectData(data: ProjectFileData): ProjectFileData {
     };
   }
 
+  // Проекты без сохраненного пояса создавались в поясе этого компьютера
+  data.projectSettings = {
+    recurrences: [],
+    timetables: [],
+    timeZone: getMachineTimeZone(),
+    ...data.projectSettings
+  };
+
   // Обновляем дату модификации
   data.metadata.modifiedAt = new Date().toISOString();
   data.metadata.version = '1.0.0';
@@ -1,5 +1,13 @@
 // Tauri API wrapper for audio processing
-import { Arrangements, TimeOfRecords, Source, ExportSettings } from '../app/context/types';
+import {
+  Arrangements,
+  TimeOfRecords,
+  Source,
+  ExportSettings,
+  ProjectSettings,
+  RecurrenceRule,
+  Timetable,
+} from '../app/context/types';
 
 // Утилитарная функция для безопасной проверки Tauri
 function checkTauriAvailability(): boolean {
@@ -56,15 +64,54 @@ export interface TauriExportRequest {
   sources: TauriSource[];
   arrangements: Record<string, unknown[]>;
   time_of_records: Record<string, unknown>;
+  recurrences?: RecurrenceRule[];
+  timetables?: Timetable[];
+  time_zone?: string;
+  project_name?: string;
   settings: ExportSettings;
   record_name: string;
 }
 
+// Объявление после разворачивания правил, расписаний и цепочек relativeTo
+export interface TauriArrangement {
+  id: string;
+  typeId: string | null;
+  playingTime: {
+    start: string;
+    end: string;
+  };
+  loudness: number | null;
+  fadeIn: boolean;
+  fadeOut: boolean;
+  fixedTime: 'start' | 'end' | 'center' | null;
+  relativeTo: {
+    arrangementId: string;
+    edge: 'start' | 'end';
+    offsetMs: number;
+  } | null;
+}
+
 export interface TauriExportProgress {
-  stage: 'loading' | 'processing' | 'encoding' | 'completed' | 'error';
+  stage: 'loading' | 'processing' | 'encoding' | 'completed' | 'cancelled' | 'error';
   progress: number; // 0-100
   message: string;
   record_name?: string;
+  elapsed_ms?: number;
+  eta_ms?: number;
+  job_id?: string;
+}
+
+export interface TauriFormatSupport {
+  extension: string;
+  encoder: string;
+  available: boolean;
+}
+
+export interface TauriFfmpegInfo {
+  path: string;
+  version: string;
+  encoders: string[];
+  formats: TauriFormatSupport[];
 }
 
 export class TauriAudioAPI {
@@ -94,6 +141,7 @@ export class TauriAudioAPI {
     sources: Source[],
     arrangements: Arrangements,
     timeOfRecords: TimeOfRecords,
+    project: ProjectSettings,
     settings: ExportSettings,
     recordName: string,
     outputDir?: string
@@ -132,6 +180,9 @@ export class TauriAudioAPI {
       sources: tauriSources,
       arrangements,
       time_of_records: timeOfRecords,
+      recurrences: project.recurrences,
+      timetables: project.timetables,
+      time_zone: project.timeZone,
       settings,
       record_name: recordName,
     };
@@ -153,6 +204,49 @@ export class TauriAudioAPI {
     }
   }
 
+  // Объявления записи после разворачивания правил повторения, расписаний уроков
+  // и цепочек relativeTo.
+  // Файлы источников не нужны: длительность берется из обрезки
+  async resolveArrangements(
+    sources: Source[],
+    arrangements: Arrangements,
+    timeOfRecords: TimeOfRecords,
+    project: ProjectSettings,
+    settings: ExportSettings,
+    recordName: string
+  ): Promise<TauriArrangement[]> {
+    const request: TauriExportRequest = {
+      sources: sources.map((source) => ({
+        title: source.title,
+        file_path: '',
+        id: source.id,
+        typeId: source.typeId,
+        cut: source.cut,
+      })),
+      arrangements,
+      time_of_records: timeOfRecords,
+      recurrences: project.recurrences,
+      timetables: project.timetables,
+      time_zone: project.timeZone,
+      settings,
+      record_name: recordName,
+    };
+
+    try {
+      if (!checkTauriAvailability()) {
+        throw new Error('Tauri API недоступен');
+      }
+
+      const { invoke } = await import('@tauri-apps/api/core');
+      return await invoke('resolve_arrangements', {
+        request,
+        recordName,
+      }) as TauriArrangement[];
+    } catch (error) {
+      throw new Error(`Ошибка расчета объявлений: ${error}`);
+    }
+  }
+
   // Сохраняет Blob источника во временный файл
   private async saveSourceToTempFile(source: Source): Promise<string> {
     // Пока используем упрощенный подход - отправляем команду в Rust для сохранения файла
@@ -297,7 +391,7 @@ export class TauriAudioAPI {
   }
 
   // Проверка доступности FFmpeg
-  static async checkFFmpegAvailability(): Promise<{ available: boolean; message: string }> {
+  static async checkFFmpegAvailability(): Promise<{ available: boolean; message: string; info?: TauriFfmpegInfo }> {
     try {
       if (!checkTauriAvailability()) {
         return { 
@@ -307,15 +401,34 @@ export class TauriAudioAPI {
       }
 
       const { invoke } = await import('@tauri-apps/api/core');
-      const result = await invoke('check_ffmpeg_availability') as string;
-      return { available: true, message: result };
+      const info = await invoke('check_ffmpeg_availability') as TauriFfmpegInfo;
+      const formats = info.formats.filter(f => f.available).map(f => f.extension).join(', ');
+      return {
+        available: true,
+        message: `FFmpeg ${info.version} найден: ${info.path} (форматы: ${formats})`,
+        info
+      };
     } catch (error) {
       return { 
         available: false, 
-        message: `FFmpeg недоступен: ${error}. Пожалуйста, убедитесь что FFmpeg включен в bundle приложения.`
+        message: `FFmpeg недоступен: ${error}. Пожалуйста, убедитесь что FFmpeg включен в bundle приложения, указан в настройках или доступен в PATH.`
       };
     }
   }
+
+  // Путь к FFmpeg из настроек пользователя (null — сбросить); сохраняется между запусками
+  static async setFFmpegPath(path: string | null): Promise<TauriFfmpegInfo> {
+    if (!checkTauriAvailability()) {
+      throw new Error('Tauri API недоступен');
+    }
+
+    const { invoke } = await import('@tauri-apps/api/core');
+    try {
+      return await invoke('set_ffmpeg_path', { path }) as TauriFfmpegInfo;
+    } catch (error) {
+      throw new Error(`Не удалось использовать FFmpeg: ${error}`);
+    }
+  }
 }
 
 // Singleton экземпляр
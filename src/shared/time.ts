@@ -74,3 +74,17 @@ export function getSecondsFromDateTime(value: DateTime): number {
 export function convertDateTimeToSeconds(dateTime: DateTime): number {
   return getSecondsFromDateTime(dateTime);
 }
+
+// Часовой пояс компьютера (имя IANA) — пояс по умолчанию для новых проектов
+export function getMachineTimeZone(): string {
+  return Intl.DateTimeFormat().resolvedOptions().timeZone;
+}
+
+// Пояса IANA для выбора в настройках проекта; текущий пояс проекта всегда в списке
+export function getTimeZoneOptions(current: string): { value: string; content: string }[] {
+  const supported: string[] =
+    (Intl as unknown as { supportedValuesOf?: (key: string) => string[] })
+      .supportedValuesOf?.("timeZone") ?? [getMachineTimeZone()];
+  const zones = supported.includes(current) ? supported : [current, ...supported];
+  return zones.map((zone) => ({ value: zone, content: zone }));
+}